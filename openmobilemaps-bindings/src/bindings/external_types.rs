@@ -3,32 +3,50 @@
 // This Source Code Form is subject to the terms of the Mozilla Public
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+
+use crate::cache::TileCache;
 use crate::ffi::*;
 use autocxx::prelude::*;
 
-use super::impls::DefaultLoaderInterface;
+use super::impls::{data_result, empty_texture_result, DefaultLoaderInterface};
 use crate::{LoaderInterfaceTrait, Tiled2dMapLayerConfigTrait};
 
 pub struct LoaderInterfaceWrapperImpl(pub Box<dyn LoaderInterfaceTrait>);
 
 impl Default for LoaderInterfaceWrapperImpl {
     fn default() -> Self {
-        Self(Box::new(DefaultLoaderInterface(false)))
+        Self(Box::new(DefaultLoaderInterface::new(false)))
     }
 }
 impl LoaderInterfaceWrapperImpl {
     pub fn new(ignore_network_error: bool) -> Self {
-         Self(Box::new(DefaultLoaderInterface(ignore_network_error)))
+         Self(Box::new(DefaultLoaderInterface::new(ignore_network_error)))
+    }
+
+    /// Uses `cache` for downloaded tiles instead of the default disk cache in `tiles/`.
+    /// Passing `None` disables caching.
+    pub fn with_cache(cache: Option<Arc<dyn TileCache>>, ignore_network_error: bool) -> Self {
+        Self(Box::new(DefaultLoaderInterface::with_cache(cache, ignore_network_error)))
     }
 }
 
 impl LoaderInterfaceWrapperImpl {
+    // A panic must not unwind into maps-core, it is reported as a failed load instead.
+
     pub fn loadTextureWrapper(
         &self,
         url: &cxx::CxxString,
         etag: cxx::UniquePtr<cxx::CxxString>,
     ) -> cxx::UniquePtr<TextureLoaderResult> {
-        self.0.loadTextureWrapper(url, etag)
+        match catch_unwind(AssertUnwindSafe(|| self.0.loadTextureWrapper(url, etag))) {
+            Ok(result) => result,
+            Err(_) => {
+                log::error!("Loader panicked while loading texture {url}");
+                empty_texture_result(LoaderStatus::ERROR_OTHER, "Loader panicked")
+            }
+        }
     }
 
     pub fn loadDataWrapper(
@@ -36,7 +54,13 @@ impl LoaderInterfaceWrapperImpl {
         url: &cxx::CxxString,
         etag: cxx::UniquePtr<cxx::CxxString>,
     ) -> cxx::UniquePtr<DataLoaderResult> {
-        self.0.loadDataWrapper(url, etag)
+        match catch_unwind(AssertUnwindSafe(|| self.0.loadDataWrapper(url, etag))) {
+            Ok(result) => result,
+            Err(_) => {
+                log::error!("Loader panicked while loading data {url}");
+                data_result(&[], LoaderStatus::ERROR_OTHER, None, "Loader panicked")
+            }
+        }
     }
 }
 
@@ -45,7 +69,7 @@ pub struct Tiled2dMapLayerConfigWrapperImpl(pub Box<dyn Tiled2dMapLayerConfigTra
 
 impl Tiled2dMapLayerConfigWrapperImpl {
     pub fn getCoordinateSystemIdentifier(&self) -> cxx::UniquePtr<cxx::CxxString> {
-        CoordinateSystemIdentifiers::EPSG3857()
+        self.0.getCoordinateSystemIdentifier()
     }
 
     pub fn getTileUrl(&self, x: i32, y: i32, t: i32, zoom: i32) -> cxx::UniquePtr<cxx::CxxString> {
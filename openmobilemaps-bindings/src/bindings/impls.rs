@@ -4,14 +4,18 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use std::str::FromStr;
-use std::sync::mpsc::Sender;
+use std::io::Read;
+use std::sync::{mpsc::Sender, Arc};
 
 use autocxx::{subclass::*, WithinUniquePtr};
 use autocxx_macro::subclass;
 use cxx::SharedPtr;
 use image::GenericImageView;
 
+use crate::cache::{
+    CacheEntry, CachePolicy, DecodedTile, DecodedTileCache, DiskCache, TileCache,
+    DEFAULT_MAX_SIZE,
+};
 use crate::ffi;
 use crate::ffi::*;
 use crate::LoaderInterfaceTrait;
@@ -25,9 +29,7 @@ pub struct MapReadyCallbackInterfaceImpl {
 impl MapReadyCallbackInterface_methods for MapReadyCallbackInterfaceImpl {
     fn stateDidUpdate(&mut self, state: LayerReadyState) {
         if let Some(sender) = self.sender.as_ref() {
-            if state == LayerReadyState::READY {
-                let _ = sender.send(LayerReadyState::READY);
-            }
+            let _ = sender.send(state);
         }
     }
 }
@@ -59,7 +61,7 @@ impl IconInfoInterface_methods for IconInfoInterfaceImpl {
         let mut interface = TextureHolderInterfaceImpl {
             image_width: self.image_width,
             image_height: self.image_height,
-            texture_data: self.texture_data.clone(),
+            texture_data: Arc::new(self.texture_data.clone()),
             ..Default::default()
         };
         let mut load_result = TextureHolderInterfaceImpl::new_cpp_owned(interface);
@@ -112,12 +114,14 @@ impl MapCallbackInterface_methods for MapCallbackInterfaceImpl {
     }
 }
 
+const TRANSPARENT_PIXEL: [u8; 4] = [0; 4];
+
 #[subclass(superclass("TextureHolderInterface"))]
 #[derive(Default)]
 pub struct TextureHolderInterfaceImpl {
     image_width: usize,
     image_height: usize,
-    texture_data: Vec<u8>,
+    texture_data: Arc<Vec<u8>>,
     usage_counter: usize,
     id: u32,
     attached: bool,
@@ -142,16 +146,19 @@ impl TextureHolderInterface_methods for TextureHolderInterfaceImpl {
 
     fn attachToGraphics(&mut self) -> i32 {
         if !self.attached {
+            // empty tiles, e.g. of ignored network errors, are drawn as a transparent pixel
+            let complete = self.image_width > 0
+                && self.image_height > 0
+                && self.texture_data.len() >= self.image_width * self.image_height * 4;
+            let (width, height, pixels) = if complete {
+                (self.image_width, self.image_height, self.texture_data.as_ptr())
+            } else {
+                (1, 1, TRANSPARENT_PIXEL.as_ptr())
+            };
             unsafe {
                 let internal_format = gl::RGBA;
                 log::debug!("load texture with gl");
-                log::debug!(
-                    "datalength: {} {} {} {}",
-                    self.texture_data[0],
-                    self.texture_data[1],
-                    self.texture_data[2],
-                    self.texture_data[3]
-                );
+                log::debug!("datalength: {}", self.texture_data.len());
                 unsafe {
                     gl::GenTextures(1, &mut self.id);
 
@@ -165,12 +172,12 @@ impl TextureHolderInterface_methods for TextureHolderInterfaceImpl {
                         gl::TEXTURE_2D,
                         0,
                         internal_format as i32,
-                        self.image_width as i32,
-                        self.image_height as i32,
+                        width as i32,
+                        height as i32,
                         0,
                         gl::RGBA,
                         gl::UNSIGNED_BYTE,
-                        self.texture_data.as_ptr() as *const _,
+                        pixels as *const _,
                     );
                     // gl::GenerateMipmap(gl::TEXTURE_2D);
                 }
@@ -192,7 +199,228 @@ impl TextureHolderInterface_methods for TextureHolderInterfaceImpl {
     }
 }
 
-pub struct DefaultLoaderInterface(pub bool);
+pub struct DefaultLoaderInterface {
+    /// Report failed texture loads as empty tiles instead of errors.
+    ignore_network_error: bool,
+    agent: ureq::Agent,
+    cache: Option<Arc<dyn TileCache>>,
+    decoded: Arc<DecodedTileCache>,
+}
+
+/// Bytes of a tile together with the etag they were served with.
+struct Fetched {
+    data: Vec<u8>,
+    etag: Option<String>,
+    /// How long the bytes, and anything decoded from them, may be reused.
+    policy: CachePolicy,
+}
+
+/// Why a tile could not be loaded, as reported back to maps-core.
+pub(crate) struct LoadFailure {
+    pub status: LoaderStatus,
+    /// Passed on as `errorCode` of the loader result.
+    pub error_code: String,
+}
+
+impl LoadFailure {
+    pub(crate) fn new(url: &str, status: LoaderStatus, error_code: impl Into<String>) -> Self {
+        let error_code = error_code.into();
+        log::warn!("Failed to load {url}: {error_code}");
+        Self { status, error_code }
+    }
+
+    /// The tile did not change since maps-core loaded it, which is not an error.
+    fn not_modified() -> Self {
+        Self {
+            status: LoaderStatus::NOOP,
+            error_code: String::new(),
+        }
+    }
+}
+
+impl DefaultLoaderInterface {
+    pub fn new(ignore_network_error: bool) -> Self {
+        let cache = match DiskCache::open("tiles", DEFAULT_MAX_SIZE) {
+            Ok(cache) => Some(Arc::new(cache) as Arc<dyn TileCache>),
+            Err(e) => {
+                log::warn!("Tile cache disabled: {e:#}");
+                None
+            }
+        };
+        Self::with_cache(cache, ignore_network_error)
+    }
+
+    pub fn with_cache(cache: Option<Arc<dyn TileCache>>, ignore_network_error: bool) -> Self {
+        Self {
+            ignore_network_error,
+            agent: ureq::Agent::new(),
+            cache,
+            decoded: DecodedTileCache::shared(),
+        }
+    }
+
+    /// Uses `decoded` instead of the process wide cache of decoded tiles.
+    pub fn with_decoded_cache(mut self, decoded: Arc<DecodedTileCache>) -> Self {
+        self.decoded = decoded;
+        self
+    }
+
+    /// Loads the bytes behind `url`. Fresh cache entries are used as they are, stale ones are
+    /// revalidated with `If-None-Match`, using the cached etag or the one maps-core passed in.
+    fn fetch(&self, url: &str, etag: Option<&str>) -> Result<Fetched, LoadFailure> {
+        let cached = self.cache.as_ref().and_then(|cache| cache.get(url));
+        if let Some(entry) = &cached {
+            if entry.is_fresh() {
+                return Ok(Fetched {
+                    data: entry.data.clone(),
+                    etag: entry.etag.clone(),
+                    policy: CachePolicy::Store(entry.expires),
+                });
+            }
+        }
+
+        let mut request = self.agent.get(url);
+        let known_etag = cached
+            .as_ref()
+            .and_then(|entry| entry.etag.as_deref())
+            .or(etag);
+        if let Some(known_etag) = known_etag {
+            request = request.set("If-None-Match", known_etag);
+        }
+        let response = match request.call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(status, _)) => {
+                return Err(LoadFailure::new(
+                    url,
+                    status_for_response(status),
+                    format!("HTTP status {status}"),
+                ));
+            }
+            Err(ureq::Error::Transport(transport)) => {
+                let status = match transport.kind() {
+                    ureq::ErrorKind::InvalidUrl | ureq::ErrorKind::UnknownScheme => {
+                        LoaderStatus::ERROR_OTHER
+                    }
+                    _ => LoaderStatus::ERROR_NETWORK,
+                };
+                return Err(LoadFailure::new(url, status, transport.to_string()));
+            }
+        };
+        let policy = CachePolicy::from_headers(
+            response.header("Cache-Control"),
+            response.header("Expires"),
+        );
+
+        if response.status() == 304 {
+            let Some(entry) = cached else {
+                // maps-core still holds the tile for the etag it passed in
+                return Err(LoadFailure::not_modified());
+            };
+            if let (Some(cache), CachePolicy::Store(expires)) = (&self.cache, policy) {
+                cache.touch(url, expires);
+            }
+            return Ok(Fetched {
+                data: entry.data,
+                etag: entry.etag,
+                policy,
+            });
+        }
+
+        let response_etag = response.header("ETag").map(str::to_owned);
+        let mut databytes = vec![];
+        if let Err(e) = response.into_reader().read_to_end(&mut databytes) {
+            return Err(LoadFailure::new(
+                url,
+                LoaderStatus::ERROR_NETWORK,
+                format!("Failed to read response body: {e}"),
+            ));
+        }
+
+        if let (Some(cache), CachePolicy::Store(expires)) = (&self.cache, policy) {
+            let entry = CacheEntry {
+                data: databytes,
+                etag: response_etag,
+                expires,
+            };
+            cache.put(url, &entry);
+            return Ok(Fetched {
+                data: entry.data,
+                etag: entry.etag,
+                policy,
+            });
+        }
+        Ok(Fetched {
+            data: databytes,
+            etag: response_etag,
+            policy,
+        })
+    }
+}
+
+fn status_for_response(status: u16) -> LoaderStatus {
+    match status {
+        400 => LoaderStatus::ERROR_400,
+        404 => LoaderStatus::ERROR_404,
+        _ => LoaderStatus::ERROR_NETWORK,
+    }
+}
+
+pub(crate) fn texture_result(tile: DecodedTile) -> cxx::UniquePtr<TextureLoaderResult> {
+    let interface = TextureHolderInterfaceImpl {
+        image_width: tile.width,
+        image_height: tile.height,
+        texture_data: tile.rgba,
+        ..Default::default()
+    };
+    let load_result = TextureHolderInterfaceImpl::new_cpp_owned(interface);
+    let tex_holder_iface =
+        TextureHolderInterfaceImpl::as_TextureHolderInterface_unique_ptr(load_result);
+    let tex_holder_iface = transform_texture_holder_interface(tex_holder_iface);
+    make_loader_result(
+        tex_holder_iface,
+        LoaderStatus::OK,
+        &make_string(tile.etag.as_deref().unwrap_or_default()),
+        &make_string(""),
+    )
+}
+
+pub(crate) fn empty_texture_result(
+    status: LoaderStatus,
+    error_code: &str,
+) -> cxx::UniquePtr<TextureLoaderResult> {
+    let load_result = TextureHolderInterfaceImpl::default_cpp_owned();
+    let tex_holder_iface =
+        TextureHolderInterfaceImpl::as_TextureHolderInterface_unique_ptr(load_result);
+    let tex_holder_iface = transform_texture_holder_interface(tex_holder_iface);
+    make_loader_result(
+        tex_holder_iface,
+        status,
+        &make_string(""),
+        &make_string(error_code),
+    )
+}
+
+pub(crate) fn data_result(
+    data: &[u8],
+    status: LoaderStatus,
+    etag: Option<&str>,
+    error_code: &str,
+) -> cxx::UniquePtr<DataLoaderResult> {
+    crate::LoaderInterfaceWrapperMod::make_data_loader_result(
+        data,
+        status,
+        &make_string(etag.unwrap_or_default()),
+        &make_string(error_code),
+    )
+}
+
+/// maps-core passes a missing etag as an empty string.
+fn etag_arg(etag: &cxx::UniquePtr<cxx::CxxString>) -> Option<&str> {
+    etag.as_ref()
+        .and_then(|etag| etag.to_str().ok())
+        .filter(|etag| !etag.is_empty())
+}
+
 impl Drop for DefaultLoaderInterface {
     fn drop(&mut self) {
         log::debug!("Drop default loader interface");
@@ -204,76 +432,50 @@ impl LoaderInterfaceTrait for DefaultLoaderInterface {
         url: &cxx::CxxString,
         etag: cxx::UniquePtr<cxx::CxxString>,
     ) -> cxx::UniquePtr<TextureLoaderResult> {
-        let u = url::Url::parse(url.to_str().unwrap()).unwrap();
-        let p = u.path();
-        let path = std::path::PathBuf::from_str(&format!("tiles/{p}")).unwrap();
-        let mut databytes = vec![];
-        if !path.exists() {
-            let data = match ureq::get(url.to_str().unwrap()).call() {
-                Ok(data) => data,
-                Err(e) => {
-                    let load_result = TextureHolderInterfaceImpl::default_cpp_owned();
-                    let tex_holder_iface =
-                        TextureHolderInterfaceImpl::as_TextureHolderInterface_unique_ptr(
-                            load_result,
-                        );
-                    let tex_holder_iface = transform_texture_holder_interface(tex_holder_iface);
-                    if self.0 {
-                        return make_loader_result(tex_holder_iface, LoaderStatus::OK);
-                    } else {
-                        match e.into_response() {
-                            Some(response) => {
-                                return make_loader_result(
-                                    tex_holder_iface,
-                                    if response.status() == 400 {
-                                        LoaderStatus::ERROR_400
-                                    } else if response.status() == 404 {
-                                        LoaderStatus::ERROR_404
-                                    } else {
-                                        println!("error_network");
-                                        LoaderStatus::ERROR_NETWORK
-                                    },
-                                );
-                            }
-                            _ => return make_loader_result(tex_holder_iface, LoaderStatus::ERROR_OTHER),
-                        }
-                    }
-                }
-            };
-            let status_code = data.status();
-
-            let Ok(_) = data.into_reader().read_to_end(&mut databytes) else {
-                let load_result = TextureHolderInterfaceImpl::default_cpp_owned();
-                let tex_holder_iface =
-                    TextureHolderInterfaceImpl::as_TextureHolderInterface_unique_ptr(load_result);
-                let tex_holder_iface = transform_texture_holder_interface(tex_holder_iface);
-                return make_loader_result(tex_holder_iface, LoaderStatus::ERROR_OTHER);
-            };
-
-            std::fs::write(&format!("tiles/{p}"), &databytes);
-        } else {
-            databytes = std::fs::read(path).unwrap();
+        let Ok(url) = url.to_str() else {
+            log::warn!("Tile URL is not valid UTF-8: {url:?}");
+            return empty_texture_result(LoaderStatus::ERROR_OTHER, "URL is not valid UTF-8");
+        };
+        if let Some(tile) = self.decoded.get(url) {
+            return texture_result(tile);
         }
-        let Ok(image) = image::load_from_memory(&databytes) else {
-            let load_result = TextureHolderInterfaceImpl::default_cpp_owned();
-            let tex_holder_iface =
-                TextureHolderInterfaceImpl::as_TextureHolderInterface_unique_ptr(load_result);
-            let tex_holder_iface = transform_texture_holder_interface(tex_holder_iface);
-            return make_loader_result(tex_holder_iface, LoaderStatus::ERROR_OTHER);
+
+        let fetched = match self.fetch(url, etag_arg(&etag)) {
+            Ok(fetched) => fetched,
+            Err(failure) if matches!(failure.status, LoaderStatus::NOOP) => {
+                return empty_texture_result(LoaderStatus::NOOP, "");
+            }
+            Err(_) if self.ignore_network_error => {
+                return empty_texture_result(LoaderStatus::OK, "");
+            }
+            Err(failure) => return empty_texture_result(failure.status, &failure.error_code),
+        };
+        let image = match image::load_from_memory(&fetched.data) {
+            Ok(image) => image,
+            Err(e) => {
+                let failure = LoadFailure::new(
+                    url,
+                    LoaderStatus::ERROR_OTHER,
+                    format!("Failed to decode image: {e}"),
+                );
+                return empty_texture_result(failure.status, &failure.error_code);
+            }
         };
         let image_dimensions = image.dimensions();
-        let img_buffer = image.to_rgba8();
-        let mut interface = TextureHolderInterfaceImpl {
-            image_width: image_dimensions.0 as usize,
-            image_height: image_dimensions.1 as usize,
-            texture_data: img_buffer.to_vec(),
-            ..Default::default()
+        let tile = DecodedTile {
+            width: image_dimensions.0 as usize,
+            height: image_dimensions.1 as usize,
+            rgba: Arc::new(image.into_rgba8().into_raw()),
+            etag: fetched.etag,
+            expires: match fetched.policy {
+                CachePolicy::Store(expires) => expires,
+                CachePolicy::NoStore => None,
+            },
         };
-        let mut load_result = TextureHolderInterfaceImpl::new_cpp_owned(interface);
-        let tex_holder_iface =
-            TextureHolderInterfaceImpl::as_TextureHolderInterface_unique_ptr(load_result);
-        let tex_holder_iface = transform_texture_holder_interface(tex_holder_iface);
-        make_loader_result(tex_holder_iface, LoaderStatus::OK)
+        if let CachePolicy::Store(_) = fetched.policy {
+            self.decoded.insert(url, tile.clone());
+        }
+        texture_result(tile)
     }
 
     fn loadDataWrapper(
@@ -281,6 +483,178 @@ impl LoaderInterfaceTrait for DefaultLoaderInterface {
         url: &cxx::CxxString,
         etag: cxx::UniquePtr<cxx::CxxString>,
     ) -> cxx::UniquePtr<DataLoaderResult> {
-        todo!()
+        let Ok(url) = url.to_str() else {
+            log::warn!("Data URL is not valid UTF-8: {url:?}");
+            return data_result(&[], LoaderStatus::ERROR_OTHER, None, "URL is not valid UTF-8");
+        };
+        match self.fetch(url, etag_arg(&etag)) {
+            Ok(fetched) => data_result(&fetched.data, LoaderStatus::OK, fetched.etag.as_deref(), ""),
+            Err(failure) => data_result(&[], failure.status, None, &failure.error_code),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::Cursor;
+    use std::sync::Mutex;
+    use std::time::SystemTime;
+
+    use cxx::let_cxx_string;
+
+    use super::*;
+    use crate::bindings::external_types::LoaderInterfaceWrapperImpl;
+    use crate::cache::DEFAULT_DECODED_MAX_SIZE;
+
+    /// Serves preset entries, so that no test goes to the network for a tile.
+    #[derive(Default)]
+    struct FakeCache(Mutex<HashMap<String, CacheEntry>>);
+
+    impl FakeCache {
+        fn with(url: &str, data: Vec<u8>) -> Arc<Self> {
+            let cache = Self::default();
+            let entry = CacheEntry {
+                data,
+                etag: None,
+                expires: None,
+            };
+            cache.put(url, &entry);
+            Arc::new(cache)
+        }
+    }
+
+    impl TileCache for FakeCache {
+        fn get(&self, url: &str) -> Option<CacheEntry> {
+            self.0.lock().unwrap().get(url).cloned()
+        }
+
+        fn put(&self, url: &str, entry: &CacheEntry) {
+            self.0.lock().unwrap().insert(url.to_owned(), entry.clone());
+        }
+
+        fn touch(&self, _url: &str, _expires: Option<SystemTime>) {}
+    }
+
+    struct PanickingLoader;
+
+    impl LoaderInterfaceTrait for PanickingLoader {
+        fn loadTextureWrapper(
+            &self,
+            _url: &cxx::CxxString,
+            _etag: cxx::UniquePtr<cxx::CxxString>,
+        ) -> cxx::UniquePtr<TextureLoaderResult> {
+            panic!("texture");
+        }
+
+        fn loadDataWrapper(
+            &self,
+            _url: &cxx::CxxString,
+            _etag: cxx::UniquePtr<cxx::CxxString>,
+        ) -> cxx::UniquePtr<DataLoaderResult> {
+            panic!("data");
+        }
+    }
+
+    fn loader(cache: Arc<FakeCache>, ignore_network_error: bool) -> DefaultLoaderInterface {
+        DefaultLoaderInterface::with_cache(Some(cache), ignore_network_error)
+            .with_decoded_cache(Arc::new(DecodedTileCache::new(DEFAULT_DECODED_MAX_SIZE)))
+    }
+
+    fn load_texture(
+        loader: &dyn LoaderInterfaceTrait,
+        url: &str,
+    ) -> UniquePtr<TextureLoaderResult> {
+        let_cxx_string!(url = url);
+        loader.loadTextureWrapper(&url, make_string(""))
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = image::RgbaImage::from_pixel(width, height, image::Rgba([255, 0, 0, 255]));
+        let mut encoded = vec![];
+        image
+            .write_to(
+                &mut Cursor::new(&mut encoded),
+                image::ImageOutputFormat::Png,
+            )
+            .unwrap();
+        encoded
+    }
+
+    // nothing listens on the discard port, so connecting fails right away
+    const UNREACHABLE: &str = "http://127.0.0.1:9/0/0/0.png";
+
+    #[test]
+    fn decodes_cached_tile() {
+        let url = "https://tiles.example/1/0/0.png";
+        let result = load_texture(&loader(FakeCache::with(url, png(2, 1)), false), url);
+        assert!(loader_result_status(&result) == LoaderStatus::OK);
+        assert_eq!(loader_result_texture_width(&result), 2);
+    }
+
+    #[test]
+    fn undecodable_tile_is_error_other() {
+        let url = "https://tiles.example/1/0/0.png";
+        let cache = FakeCache::with(url, b"not an image".to_vec());
+        let result = load_texture(&loader(cache, false), url);
+        assert!(loader_result_status(&result) == LoaderStatus::ERROR_OTHER);
+        assert!(loader_result_error_code(&result)
+            .to_string_lossy()
+            .starts_with("Failed to decode image"));
+    }
+
+    #[test]
+    fn malformed_url_is_error_other() {
+        let result = load_texture(&loader(Arc::default(), false), "not a url");
+        assert!(loader_result_status(&result) == LoaderStatus::ERROR_OTHER);
+    }
+
+    #[test]
+    fn non_utf8_url_is_error_other() {
+        let_cxx_string!(url = b"https://tiles.example/\xff.png");
+        let result = loader(Arc::default(), false).loadTextureWrapper(&url, make_string(""));
+        assert!(loader_result_status(&result) == LoaderStatus::ERROR_OTHER);
+        assert_eq!(
+            loader_result_error_code(&result).to_string_lossy(),
+            "URL is not valid UTF-8"
+        );
+    }
+
+    #[test]
+    fn network_error_is_reported() {
+        let result = load_texture(&loader(Arc::default(), false), UNREACHABLE);
+        assert!(loader_result_status(&result) == LoaderStatus::ERROR_NETWORK);
+    }
+
+    #[test]
+    fn ignored_network_error_is_empty_tile() {
+        let result = load_texture(&loader(Arc::default(), true), UNREACHABLE);
+        assert!(loader_result_status(&result) == LoaderStatus::OK);
+        assert_eq!(loader_result_texture_width(&result), 0);
+    }
+
+    #[test]
+    fn loads_cached_data() {
+        let url = "https://data.example/style.json";
+        let_cxx_string!(cxx_url = url);
+        let loader = loader(FakeCache::with(url, b"{}".to_vec()), false);
+        let result = loader.loadDataWrapper(&cxx_url, make_string(""));
+        assert!(data_result_status(&result) == LoaderStatus::OK);
+        assert_eq!(data_result_len(&result), 2);
+    }
+
+    #[test]
+    fn panicking_loader_is_error_other() {
+        let loader = LoaderInterfaceWrapperImpl(Box::new(PanickingLoader));
+        let_cxx_string!(url = "https://tiles.example/1/0/0.png");
+        let texture = loader.loadTextureWrapper(&url, make_string(""));
+        assert!(loader_result_status(&texture) == LoaderStatus::ERROR_OTHER);
+        assert_eq!(
+            loader_result_error_code(&texture).to_string_lossy(),
+            "Loader panicked"
+        );
+        let data = loader.loadDataWrapper(&url, make_string(""));
+        assert!(data_result_status(&data) == LoaderStatus::ERROR_OTHER);
+        assert_eq!(data_result_len(&data), 0);
     }
 }
@@ -0,0 +1,218 @@
+// Copyright (c) 2023 Ubique Innovation AG <https://www.ubique.ch>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    time::SystemTime,
+};
+
+/// Default size limit of the shared [`DecodedTileCache`].
+pub const DEFAULT_DECODED_MAX_SIZE: usize = 128 * 1024 * 1024;
+
+lazy_static::lazy_static! {
+    static ref SHARED: Arc<DecodedTileCache> =
+        Arc::new(DecodedTileCache::new(DEFAULT_DECODED_MAX_SIZE));
+}
+
+/// A tile decoded to RGBA8, ready to be uploaded as a texture.
+#[derive(Debug, Clone)]
+pub struct DecodedTile {
+    pub width: usize,
+    pub height: usize,
+    pub rgba: Arc<Vec<u8>>,
+    pub etag: Option<String>,
+    /// Same meaning as [`super::CacheEntry::expires`].
+    pub expires: Option<SystemTime>,
+}
+
+impl DecodedTile {
+    fn is_fresh(&self) -> bool {
+        self.expires
+            .map(|expires| SystemTime::now() < expires)
+            .unwrap_or(true)
+    }
+}
+
+/// Hit and miss counters of a [`DecodedTileCache`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecodedCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub size: usize,
+}
+
+struct Slot {
+    tile: DecodedTile,
+    last_use: u64,
+}
+
+#[derive(Default)]
+struct Slots {
+    tiles: HashMap<String, Slot>,
+    clock: u64,
+    size: usize,
+}
+
+/// Bounded in-memory LRU of decoded tiles keyed by URL.
+///
+/// The loaders share [`DecodedTileCache::shared`] by default, so a tile decoded for one
+/// layer or render is reused by all others in the process.
+pub struct DecodedTileCache {
+    max_size: usize,
+    slots: Mutex<Slots>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl DecodedTileCache {
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            max_size,
+            slots: Mutex::new(Slots::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// The process wide cache used by the default loaders.
+    pub fn shared() -> Arc<DecodedTileCache> {
+        SHARED.clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Slots> {
+        self.slots.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the tile for `url` unless it is missing or expired.
+    pub fn get(&self, url: &str) -> Option<DecodedTile> {
+        let mut slots = self.lock();
+        slots.clock += 1;
+        let clock = slots.clock;
+        let tile = match slots.tiles.get_mut(url) {
+            Some(slot) if slot.tile.is_fresh() => {
+                slot.last_use = clock;
+                Some(slot.tile.clone())
+            }
+            _ => None,
+        };
+        if tile.is_some() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+        tile
+    }
+
+    pub fn insert(&self, url: &str, tile: DecodedTile) {
+        let size = tile.rgba.len();
+        if size > self.max_size {
+            return;
+        }
+        let mut slots = self.lock();
+        slots.clock += 1;
+        let last_use = slots.clock;
+        if let Some(old) = slots.tiles.insert(url.to_owned(), Slot { tile, last_use }) {
+            slots.size -= old.tile.rgba.len();
+        }
+        slots.size += size;
+
+        while slots.size > self.max_size {
+            let Some(oldest) = slots
+                .tiles
+                .iter()
+                .min_by_key(|(_, slot)| slot.last_use)
+                .map(|(url, _)| url.clone())
+            else {
+                break;
+            };
+            if let Some(slot) = slots.tiles.remove(&oldest) {
+                slots.size -= slot.tile.rgba.len();
+            }
+        }
+    }
+
+    pub fn clear(&self) {
+        *self.lock() = Slots::default();
+    }
+
+    pub fn stats(&self) -> DecodedCacheStats {
+        let slots = self.lock();
+        DecodedCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: slots.tiles.len(),
+            size: slots.size,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn tile(size: usize) -> DecodedTile {
+        DecodedTile {
+            width: 1,
+            height: size / 4,
+            rgba: Arc::new(vec![0; size]),
+            etag: None,
+            expires: None,
+        }
+    }
+
+    #[test]
+    fn counts_hits_and_misses() {
+        let cache = DecodedTileCache::new(1024);
+        assert!(cache.get("a").is_none());
+        cache.insert("a", tile(64));
+        assert_eq!(cache.get("a").unwrap().rgba.len(), 64);
+        assert!(cache.get("a").is_some());
+
+        let expired = DecodedTile {
+            expires: Some(SystemTime::now() - Duration::from_secs(1)),
+            ..tile(64)
+        };
+        cache.insert("b", expired);
+        assert!(cache.get("b").is_none());
+
+        assert_eq!(
+            cache.stats(),
+            DecodedCacheStats {
+                hits: 2,
+                misses: 2,
+                entries: 2,
+                size: 128,
+            }
+        );
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache = DecodedTileCache::new(256);
+        cache.insert("a", tile(100));
+        cache.insert("b", tile(100));
+        // reading a makes b the least recently used tile
+        assert!(cache.get("a").is_some());
+        cache.insert("c", tile(100));
+
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_some());
+        assert_eq!(cache.stats().size, 200);
+
+        // tiles over the budget are never cached
+        cache.insert("d", tile(300));
+        assert!(cache.get("d").is_none());
+        assert_eq!(cache.stats().entries, 2);
+    }
+}
@@ -0,0 +1,474 @@
+// Copyright (c) 2023 Ubique Innovation AG <https://www.ubique.ch>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+
+mod decoded;
+pub use decoded::*;
+
+/// Default size limit of the [`DiskCache`] used by the default loader.
+pub const DEFAULT_MAX_SIZE: u64 = 256 * 1024 * 1024;
+
+/// A cached response body together with what is needed to revalidate it.
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    pub data: Vec<u8>,
+    pub etag: Option<String>,
+    /// After this point the entry has to be revalidated before it is used. `None` means the
+    /// server sent no freshness information and the entry is used until it is evicted.
+    pub expires: Option<SystemTime>,
+}
+
+impl CacheEntry {
+    pub fn is_fresh(&self) -> bool {
+        self.expires
+            .map(|expires| SystemTime::now() < expires)
+            .unwrap_or(true)
+    }
+}
+
+/// Storage for downloaded tiles, keyed by URL.
+pub trait TileCache: Send + Sync {
+    fn get(&self, url: &str) -> Option<CacheEntry>;
+    fn put(&self, url: &str, entry: &CacheEntry);
+    /// Updates the expiry of an entry the server confirmed as unchanged.
+    fn touch(&self, url: &str, expires: Option<SystemTime>);
+}
+
+/// What the response headers allow us to do with a response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// The response must not be stored.
+    NoStore,
+    /// The response may be stored and used until the given time (forever if `None`).
+    Store(Option<SystemTime>),
+}
+
+impl CachePolicy {
+    /// Derives the policy from the `Cache-Control` and `Expires` headers. `Cache-Control`
+    /// takes precedence, as in HTTP/1.1.
+    pub fn from_headers(cache_control: Option<&str>, expires: Option<&str>) -> Self {
+        let now = SystemTime::now();
+        if let Some(cache_control) = cache_control {
+            let mut max_age = None;
+            for directive in cache_control.split(',').map(str::trim) {
+                let (name, value) = directive
+                    .split_once('=')
+                    .map(|(name, value)| (name.trim(), Some(value.trim().trim_matches('"'))))
+                    .unwrap_or((directive, None));
+                match name.to_ascii_lowercase().as_str() {
+                    "no-store" => return CachePolicy::NoStore,
+                    "no-cache" => return CachePolicy::Store(Some(now)),
+                    "max-age" => max_age = value.and_then(|v| v.parse::<u64>().ok()),
+                    _ => {}
+                }
+            }
+            if let Some(max_age) = max_age {
+                return CachePolicy::Store(Some(now + Duration::from_secs(max_age)));
+            }
+        }
+        if let Some(expires) = expires {
+            // an invalid date means already expired
+            let expires = httpdate::parse_http_date(expires).unwrap_or(now);
+            return CachePolicy::Store(Some(expires));
+        }
+        CachePolicy::Store(None)
+    }
+}
+
+struct IndexEntry {
+    size: u64,
+    /// Position in [`DiskIndex::by_access`].
+    last_access: u64,
+}
+
+#[derive(Default)]
+struct DiskIndex {
+    entries: HashMap<String, IndexEntry>,
+    /// Keys ordered from least to most recently used.
+    by_access: BTreeMap<u64, String>,
+    clock: u64,
+    total_size: u64,
+}
+
+impl DiskIndex {
+    fn insert(&mut self, key: String, size: u64) {
+        self.remove(&key);
+        self.clock += 1;
+        self.by_access.insert(self.clock, key.clone());
+        self.entries.insert(
+            key,
+            IndexEntry {
+                size,
+                last_access: self.clock,
+            },
+        );
+        self.total_size += size;
+    }
+
+    fn touch(&mut self, key: &str) {
+        let Some(entry) = self.entries.get_mut(key) else {
+            return;
+        };
+        self.by_access.remove(&entry.last_access);
+        self.clock += 1;
+        entry.last_access = self.clock;
+        self.by_access.insert(self.clock, key.to_owned());
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.by_access.remove(&entry.last_access);
+            self.total_size -= entry.size;
+        }
+    }
+
+    fn least_recently_used(&self) -> Option<String> {
+        self.by_access.values().next().cloned()
+    }
+}
+
+/// A [`TileCache`] that keeps one file per tile below `root` and evicts the least recently
+/// used tiles once the cache grows over `max_size` bytes.
+///
+/// Every tile is stored as `<hash>.bin` with a `<hash>.meta` sidecar holding the URL, the etag
+/// and the expiry. The index is rebuilt from the directory when the cache is opened, using the
+/// modification times as access times.
+pub struct DiskCache {
+    root: PathBuf,
+    max_size: u64,
+    index: Mutex<DiskIndex>,
+}
+
+impl DiskCache {
+    pub fn open(root: impl Into<PathBuf>, max_size: u64) -> anyhow::Result<Self> {
+        let root = root.into();
+        std::fs::create_dir_all(&root)
+            .with_context(|| format!("Failed to create cache directory {}", root.display()))?;
+
+        let mut files = Vec::new();
+        let dir = std::fs::read_dir(&root)
+            .with_context(|| format!("Failed to read cache directory {}", root.display()))?;
+        for file in dir.flatten() {
+            let path = file.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("bin") {
+                continue;
+            }
+            let (Some(key), Ok(metadata)) = (
+                path.file_stem().and_then(|s| s.to_str()).map(str::to_owned),
+                file.metadata(),
+            ) else {
+                continue;
+            };
+            files.push((
+                metadata.modified().unwrap_or(UNIX_EPOCH),
+                key,
+                metadata.len(),
+            ));
+        }
+        files.sort();
+        let mut index = DiskIndex::default();
+        for (_, key, size) in files {
+            index.insert(key, size);
+        }
+
+        let cache = Self {
+            root,
+            max_size,
+            index: Mutex::new(index),
+        };
+        cache.evict(&mut cache.lock());
+        Ok(cache)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn size(&self) -> u64 {
+        self.lock().total_size
+    }
+
+    /// The index stays usable if a thread panicked while holding it, at worst it is out of
+    /// sync with the files, which `get` tolerates.
+    fn lock(&self) -> MutexGuard<'_, DiskIndex> {
+        self.index.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn key(url: &str) -> String {
+        // FNV-1a, stable across builds unlike the std hasher
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in url.bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        format!("{hash:016x}")
+    }
+
+    fn data_path(&self, key: &str) -> PathBuf {
+        self.root.join(format!("{key}.bin"))
+    }
+
+    fn meta_path(&self, key: &str) -> PathBuf {
+        self.root.join(format!("{key}.meta"))
+    }
+
+    fn write_meta(&self, key: &str, url: &str, etag: Option<&str>, expires: Option<SystemTime>) {
+        let mut meta = format!("url={url}\n");
+        if let Some(etag) = etag {
+            meta.push_str(&format!("etag={etag}\n"));
+        }
+        if let Some(expires) = expires {
+            let secs = expires
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            meta.push_str(&format!("expires={secs}\n"));
+        }
+        if let Err(e) = std::fs::write(self.meta_path(key), meta) {
+            log::warn!("Failed to write cache metadata for {url}: {e}");
+        }
+    }
+
+    fn read_meta(&self, key: &str) -> Option<(String, Option<String>, Option<SystemTime>)> {
+        let meta = std::fs::read_to_string(self.meta_path(key)).ok()?;
+        let mut url = None;
+        let mut etag = None;
+        let mut expires = None;
+        for line in meta.lines() {
+            match line.split_once('=') {
+                Some(("url", value)) => url = Some(value.to_owned()),
+                Some(("etag", value)) => etag = Some(value.to_owned()),
+                Some(("expires", value)) => {
+                    expires = value
+                        .parse::<u64>()
+                        .ok()
+                        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+                }
+                _ => {}
+            }
+        }
+        Some((url?, etag, expires))
+    }
+
+    fn remove(&self, index: &mut DiskIndex, key: &str) {
+        index.remove(key);
+        let _ = std::fs::remove_file(self.data_path(key));
+        let _ = std::fs::remove_file(self.meta_path(key));
+    }
+
+    fn evict(&self, index: &mut DiskIndex) {
+        while index.total_size > self.max_size {
+            let Some(key) = index.least_recently_used() else {
+                break;
+            };
+            log::debug!("Evicting {key} from tile cache");
+            self.remove(index, &key);
+        }
+    }
+}
+
+impl TileCache for DiskCache {
+    fn get(&self, url: &str) -> Option<CacheEntry> {
+        let key = Self::key(url);
+        let mut index = self.lock();
+        index.entries.get(&key)?;
+
+        let Some((stored_url, etag, expires)) = self.read_meta(&key) else {
+            // without its metadata the tile is useless
+            self.remove(&mut index, &key);
+            return None;
+        };
+        if stored_url != url {
+            // hash collision, the entry belongs to another tile
+            return None;
+        }
+        let Ok(data) = std::fs::read(self.data_path(&key)) else {
+            self.remove(&mut index, &key);
+            return None;
+        };
+        index.touch(&key);
+        Some(CacheEntry {
+            data,
+            etag,
+            expires,
+        })
+    }
+
+    fn put(&self, url: &str, entry: &CacheEntry) {
+        let key = Self::key(url);
+        let mut index = self.lock();
+        self.remove(&mut index, &key);
+        if entry.data.len() as u64 > self.max_size {
+            return;
+        }
+        if let Err(e) = std::fs::write(self.data_path(&key), &entry.data) {
+            log::warn!("Failed to write {url} to the tile cache: {e}");
+            return;
+        }
+        self.write_meta(&key, url, entry.etag.as_deref(), entry.expires);
+
+        index.insert(key, entry.data.len() as u64);
+        self.evict(&mut index);
+    }
+
+    fn touch(&self, url: &str, expires: Option<SystemTime>) {
+        let key = Self::key(url);
+        let mut index = self.lock();
+        let Some((stored_url, etag, _)) = self.read_meta(&key) else {
+            return;
+        };
+        if stored_url != url {
+            return;
+        }
+        self.write_meta(&key, url, etag.as_deref(), expires);
+        index.touch(&key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory for one test, removed again when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "openmobilemaps-cache-{name}-{}",
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn entry(size: usize) -> CacheEntry {
+        CacheEntry {
+            data: vec![7; size],
+            etag: Some("\"v1\"".into()),
+            expires: None,
+        }
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let dir = TempDir::new("evict");
+        let cache = DiskCache::open(&dir.0, 300).unwrap();
+        cache.put("a", &entry(100));
+        cache.put("b", &entry(100));
+        cache.put("c", &entry(100));
+        // reading a makes b the least recently used tile
+        assert!(cache.get("a").is_some());
+        cache.put("d", &entry(100));
+
+        assert_eq!(cache.size(), 300);
+        assert!(cache.get("b").is_none());
+        for url in ["a", "c", "d"] {
+            assert!(cache.get(url).is_some(), "{url} was evicted");
+        }
+    }
+
+    #[test]
+    fn skips_tiles_larger_than_the_cache() {
+        let dir = TempDir::new("large");
+        let cache = DiskCache::open(&dir.0, 100).unwrap();
+        cache.put("a", &entry(50));
+        cache.put("b", &entry(101));
+
+        assert_eq!(cache.size(), 50);
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+    }
+
+    #[test]
+    fn reopens_index() {
+        let dir = TempDir::new("reopen");
+        {
+            let cache = DiskCache::open(&dir.0, 1000).unwrap();
+            cache.put("a", &entry(100));
+            cache.put("b", &entry(200));
+        }
+        let cache = DiskCache::open(&dir.0, 1000).unwrap();
+        assert_eq!(cache.size(), 300);
+        let a = cache.get("a").unwrap();
+        assert_eq!(a.data, vec![7; 100]);
+        assert_eq!(a.etag.as_deref(), Some("\"v1\""));
+
+        // a smaller limit evicts on open
+        drop(cache);
+        let cache = DiskCache::open(&dir.0, 250).unwrap();
+        assert!(cache.size() <= 250);
+    }
+
+    #[test]
+    fn forgets_tiles_without_metadata() {
+        let dir = TempDir::new("meta");
+        let cache = DiskCache::open(&dir.0, 1000).unwrap();
+        cache.put("a", &entry(100));
+        std::fs::remove_file(cache.meta_path(&DiskCache::key("a"))).unwrap();
+
+        assert!(cache.get("a").is_none());
+        assert_eq!(cache.size(), 0);
+    }
+
+    fn expiry(policy: CachePolicy) -> Option<SystemTime> {
+        match policy {
+            CachePolicy::Store(expires) => expires,
+            CachePolicy::NoStore => panic!("response was not stored"),
+        }
+    }
+
+    #[test]
+    fn policy_from_cache_control() {
+        assert_eq!(
+            CachePolicy::from_headers(Some("public, no-store"), None),
+            CachePolicy::NoStore
+        );
+        let no_cache = expiry(CachePolicy::from_headers(Some("no-cache"), None)).unwrap();
+        assert!(no_cache <= SystemTime::now());
+
+        let expires = expiry(CachePolicy::from_headers(
+            Some("max-age=3600"),
+            Some("Wed, 21 Oct 2015 07:28:00 GMT"),
+        ))
+        .unwrap();
+        let remaining = expires.duration_since(SystemTime::now()).unwrap();
+        assert!(remaining > Duration::from_secs(3500));
+        assert!(remaining <= Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn policy_from_expires() {
+        let expires = expiry(CachePolicy::from_headers(
+            Some("public"),
+            Some("Wed, 21 Oct 2015 07:28:00 GMT"),
+        ));
+        assert_eq!(expires, Some(UNIX_EPOCH + Duration::from_secs(1445412480)));
+
+        let malformed = expiry(CachePolicy::from_headers(None, Some("0"))).unwrap();
+        assert!(malformed <= SystemTime::now());
+
+        assert_eq!(
+            CachePolicy::from_headers(None, None),
+            CachePolicy::Store(None)
+        );
+    }
+}
@@ -0,0 +1,327 @@
+// Copyright (c) 2023 Ubique Innovation AG <https://www.ubique.ch>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use anyhow::bail;
+use cxx::{let_cxx_string, CxxString, UniquePtr};
+
+use crate::*;
+
+/// A position in one of the coordinate systems maps-core ships converters for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Coordinate {
+    /// WGS84 in degrees (EPSG:4326).
+    Wgs84 { lat: f64, lon: f64 },
+    /// Swiss LV95 easting and northing in meters (EPSG:2056).
+    Lv95 { e: f64, n: f64 },
+    /// Swiss LV03 easting and northing in meters (EPSG:21781).
+    Lv03 { e: f64, n: f64 },
+    /// Web mercator in meters (EPSG:3857).
+    WebMercator { x: f64, y: f64 },
+}
+
+impl Coordinate {
+    /// The maps-core identifier of the coordinate system the position is given in.
+    pub fn system_identifier(&self) -> UniquePtr<CxxString> {
+        match self {
+            Coordinate::Wgs84 { .. } => CoordinateSystemIdentifiers::EPSG4326(),
+            Coordinate::Lv95 { .. } => CoordinateSystemIdentifiers::EPSG2056(),
+            Coordinate::Lv03 { .. } => CoordinateSystemIdentifiers::EPSG21781(),
+            Coordinate::WebMercator { .. } => CoordinateSystemIdentifiers::EPSG3857(),
+        }
+    }
+
+    /// `x` and `y` as maps-core stores them, WGS84 has the longitude in `x`.
+    fn xy(&self) -> (f64, f64) {
+        match *self {
+            Coordinate::Wgs84 { lat, lon } => (lon, lat),
+            Coordinate::Lv95 { e, n } | Coordinate::Lv03 { e, n } => (e, n),
+            Coordinate::WebMercator { x, y } => (x, y),
+        }
+    }
+
+    fn from_xy(system_identifier: &CxxString, x: f64, y: f64) -> anyhow::Result<Self> {
+        let is = |identifier: UniquePtr<CxxString>| *identifier == *system_identifier;
+        if is(CoordinateSystemIdentifiers::EPSG4326()) {
+            Ok(Coordinate::Wgs84 { lat: y, lon: x })
+        } else if is(CoordinateSystemIdentifiers::EPSG2056()) {
+            Ok(Coordinate::Lv95 { e: x, n: y })
+        } else if is(CoordinateSystemIdentifiers::EPSG21781()) {
+            Ok(Coordinate::Lv03 { e: x, n: y })
+        } else if is(CoordinateSystemIdentifiers::EPSG3857()) {
+            Ok(Coordinate::WebMercator { x, y })
+        } else {
+            bail!("Unsupported coordinate system {system_identifier}")
+        }
+    }
+
+    pub fn to_coord(&self) -> UniquePtr<Coord> {
+        let (x, y) = self.xy();
+        Coord::new(self.system_identifier(), x, y, 0.0).within_unique_ptr()
+    }
+
+    pub fn from_coord(coord: &Coord) -> anyhow::Result<Self> {
+        Self::from_xy(&coord_system_identifier(coord), coord_x(coord), coord_y(coord))
+    }
+
+    /// Reprojects the position into the system with the maps-core identifier
+    /// `system_identifier`, e.g. the one of a custom map coordinate system.
+    pub fn to_coord_in(&self, system_identifier: &str) -> anyhow::Result<UniquePtr<Coord>> {
+        let_cxx_string!(to = system_identifier);
+        let converted = convert_coord(&to, &self.to_coord());
+        if converted.is_null() {
+            bail!(
+                "Can not convert from {} to {system_identifier}",
+                self.system_identifier()
+            );
+        }
+        Ok(converted)
+    }
+
+    fn convert(&self, to: UniquePtr<CxxString>) -> anyhow::Result<Self> {
+        if *to == *self.system_identifier() {
+            return Ok(*self);
+        }
+        let converted = convert_coord(&to, &self.to_coord());
+        if converted.is_null() {
+            bail!("Can not convert from {} to {to}", self.system_identifier());
+        }
+        Self::from_coord(&converted)
+    }
+
+    pub fn to_wgs84(&self) -> anyhow::Result<Self> {
+        self.convert(CoordinateSystemIdentifiers::EPSG4326())
+    }
+
+    pub fn to_lv95(&self) -> anyhow::Result<Self> {
+        self.convert(CoordinateSystemIdentifiers::EPSG2056())
+    }
+
+    pub fn to_lv03(&self) -> anyhow::Result<Self> {
+        self.convert(CoordinateSystemIdentifiers::EPSG21781())
+    }
+
+    pub fn to_web_mercator(&self) -> anyhow::Result<Self> {
+        self.convert(CoordinateSystemIdentifiers::EPSG3857())
+    }
+}
+
+/// An axis aligned rectangle, both corners are given in the same system.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CoordinateRect {
+    pub top_left: Coordinate,
+    pub bottom_right: Coordinate,
+}
+
+impl CoordinateRect {
+    /// Builds the rectangle from two corners, `bottom_right` is converted into the system
+    /// of `top_left` if needed.
+    pub fn new(top_left: Coordinate, bottom_right: Coordinate) -> anyhow::Result<Self> {
+        let bottom_right = bottom_right.convert(top_left.system_identifier())?;
+        Ok(Self {
+            top_left,
+            bottom_right,
+        })
+    }
+
+    pub fn to_rect_coord(&self) -> UniquePtr<RectCoord> {
+        RectCoord::new(self.top_left.to_coord(), self.bottom_right.to_coord()).within_unique_ptr()
+    }
+
+    pub fn from_rect_coord(rect: &RectCoord) -> anyhow::Result<Self> {
+        Ok(Self {
+            top_left: Coordinate::from_coord(&rect_top_left(rect))?,
+            bottom_right: Coordinate::from_coord(&rect_bottom_right(rect))?,
+        })
+    }
+
+    /// Reprojects the rectangle into the system with the maps-core identifier
+    /// `system_identifier`.
+    pub fn to_rect_coord_in(
+        &self,
+        system_identifier: &str,
+    ) -> anyhow::Result<UniquePtr<RectCoord>> {
+        let_cxx_string!(to = system_identifier);
+        let converted = convert_rect(&to, &self.to_rect_coord());
+        if converted.is_null() {
+            bail!(
+                "Can not convert from {} to {system_identifier}",
+                self.top_left.system_identifier()
+            );
+        }
+        Ok(converted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// maps-core converts between WGS84 and the Swiss grids with the approximate formulas
+    /// of swisstopo, which are accurate to about a meter.
+    const METERS: f64 = 1.0;
+    /// About a meter in Switzerland.
+    const DEGREES: f64 = 1e-5;
+    /// Web mercator and the shift between LV95 and LV03 are exact.
+    const EXACT_METERS: f64 = 0.01;
+
+    struct Place {
+        name: &'static str,
+        wgs84: Coordinate,
+        lv95: Coordinate,
+        lv03: Coordinate,
+        web_mercator: Coordinate,
+    }
+
+    fn places() -> [Place; 3] {
+        [
+            Place {
+                name: "Bern",
+                wgs84: Coordinate::Wgs84 {
+                    lat: 46.9480,
+                    lon: 7.4474,
+                },
+                lv95: Coordinate::Lv95 {
+                    e: 2600667.46,
+                    n: 1199657.33,
+                },
+                lv03: Coordinate::Lv03 {
+                    e: 600667.46,
+                    n: 199657.33,
+                },
+                web_mercator: Coordinate::WebMercator {
+                    x: 829040.78,
+                    y: 5933590.48,
+                },
+            },
+            Place {
+                name: "Olten",
+                wgs84: Coordinate::Wgs84 {
+                    lat: 47.3500,
+                    lon: 7.9080,
+                },
+                lv95: Coordinate::Lv95 {
+                    e: 2635465.95,
+                    n: 1244455.62,
+                },
+                lv03: Coordinate::Lv03 {
+                    e: 635465.95,
+                    n: 244455.62,
+                },
+                web_mercator: Coordinate::WebMercator {
+                    x: 880314.53,
+                    y: 5999391.28,
+                },
+            },
+            Place {
+                name: "Liestal",
+                wgs84: Coordinate::Wgs84 {
+                    lat: 47.4840,
+                    lon: 7.7340,
+                },
+                lv95: Coordinate::Lv95 {
+                    e: 2622262.66,
+                    n: 1259290.18,
+                },
+                lv03: Coordinate::Lv03 {
+                    e: 622262.66,
+                    n: 259290.18,
+                },
+                web_mercator: Coordinate::WebMercator {
+                    x: 860944.94,
+                    y: 6021436.14,
+                },
+            },
+        ]
+    }
+
+    fn assert_near(name: &str, actual: Coordinate, expected: Coordinate, tolerance: f64) {
+        assert_eq!(
+            *actual.system_identifier(),
+            *expected.system_identifier(),
+            "{name}"
+        );
+        let ((x, y), (expected_x, expected_y)) = (actual.xy(), expected.xy());
+        assert!(
+            (x - expected_x).abs() <= tolerance && (y - expected_y).abs() <= tolerance,
+            "{name}: {actual:?} is not within {tolerance} of {expected:?}"
+        );
+    }
+
+    #[test]
+    fn converts_wgs84_into_other_systems() {
+        for place in places() {
+            let wgs84 = place.wgs84;
+            assert_near(place.name, wgs84.to_lv95().unwrap(), place.lv95, METERS);
+            assert_near(place.name, wgs84.to_lv03().unwrap(), place.lv03, METERS);
+            assert_near(
+                place.name,
+                wgs84.to_web_mercator().unwrap(),
+                place.web_mercator,
+                EXACT_METERS,
+            );
+        }
+    }
+
+    #[test]
+    fn converts_into_wgs84() {
+        for place in places() {
+            for coordinate in [place.lv95, place.lv03] {
+                assert_near(
+                    place.name,
+                    coordinate.to_wgs84().unwrap(),
+                    place.wgs84,
+                    DEGREES,
+                );
+            }
+            // exact up to the rounding of the expected values to centimeters
+            assert_near(
+                place.name,
+                place.web_mercator.to_wgs84().unwrap(),
+                place.wgs84,
+                1e-7,
+            );
+        }
+    }
+
+    #[test]
+    fn shifts_between_swiss_grids() {
+        for place in places() {
+            assert_near(
+                place.name,
+                place.lv95.to_lv03().unwrap(),
+                place.lv03,
+                EXACT_METERS,
+            );
+            assert_near(
+                place.name,
+                place.lv03.to_lv95().unwrap(),
+                place.lv95,
+                EXACT_METERS,
+            );
+        }
+    }
+
+    #[test]
+    fn round_trips_through_all_systems() {
+        for place in places() {
+            let round_trip = place
+                .wgs84
+                .to_lv95()
+                .and_then(|lv95| lv95.to_lv03())
+                .and_then(|lv03| lv03.to_web_mercator())
+                .and_then(|web_mercator| web_mercator.to_wgs84())
+                .unwrap();
+            assert_near(place.name, round_trip, place.wgs84, DEGREES);
+
+            let round_trip = place
+                .lv95
+                .to_web_mercator()
+                .and_then(|web_mercator| web_mercator.to_lv95())
+                .unwrap();
+            assert_near(place.name, round_trip, place.lv95, METERS);
+        }
+    }
+}
@@ -1,5 +1,13 @@
 pub mod bindings;
+pub mod cache;
+pub mod coordinates;
+pub mod line;
+pub mod mbtiles;
 pub mod openstreetmap;
+pub mod pmtiles;
+pub mod raster;
+pub mod scheduler;
+pub mod wmts;
 
 pub use autocxx;
 pub use autocxx::cxx;
@@ -10,6 +18,11 @@ use cxx::CxxVector;
 pub use ffi::*;
 
 pub use bindings::{cxx_const_cast, cxx_shared_cast};
+pub use cache::{CacheEntry, DecodedTileCache, DiskCache, TileCache};
+pub use coordinates::{Coordinate, CoordinateRect};
+pub use scheduler::{
+    DefaultSpawner, SchedulerBuilder, SchedulerInterfaceRust, TaskReceiver, TaskSpawner,
+};
 
 use autocxx_macro::extern_rust_function;
 
@@ -42,6 +55,7 @@ include_cpp! {
     #include "Coord.h"
     #include "Color.h"
     #include "TaskInterface.h"
+    #include "TaskPriority.h"
     #include "TextureHolderInterface.h"
     #include "TextureLoaderResult.h"
     #include "DataLoaderResult.h"
@@ -94,6 +108,7 @@ include_cpp! {
     generate!("MapCamera2dInterface")
     generate!("MapConfig")
     generate!("TaskInterface")
+    generate!("TaskPriority")
     generate!("MapCoordinateSystem")
     generate!("CoordinateSystemFactory")
     generate!("RectCoord")
@@ -114,15 +129,33 @@ include_cpp! {
     generate!("transform_unique")
     generate!("transform_texture_holder_interface")
     generate!("make_loader_result")
+    generate!("loader_result_status")
+    generate!("loader_result_error_code")
+    generate!("loader_result_texture_width")
+    generate!("data_result_status")
+    generate!("data_result_len")
     generate!("down_cast_to_layer_interface")
     generate!("make_vec_zoom_level_info")
     generate!("add_zoom_level_info")
     generate!("run_task")
     generate!("get_id")
+    generate!("get_delay")
+    generate!("get_priority")
     generate!("is_graphics")
+    generate!("TaskLog")
+    generate!("make_task_log")
+    generate!("make_logging_task")
     generate!("PolygonInfo")
     generate!("to_map_callback_interface_shared_pointer")
     generate!("make_polygon_coord")
+    generate!("coord_system_identifier")
+    generate!("coord_x")
+    generate!("coord_y")
+    generate!("coord_z")
+    generate!("rect_top_left")
+    generate!("rect_bottom_right")
+    generate!("convert_coord")
+    generate!("convert_rect")
     generate!("transform_ready_state")
     generate!("transform_icon_info_interface")
     generate!("MapScene")
@@ -145,6 +178,15 @@ include_cpp! {
     generate!("SizeType")
     generate!("LineStyle")
     generate!("make_default_dash")
+    generate!("LineInfoInterface")
+    generate!("make_vec_float")
+    generate!("add_float")
+    generate!("make_vec_coord")
+    generate!("add_coord")
+    generate!("copy_coords")
+    generate!("copy_line_style")
+    generate!("make_line_style")
+    generate!("line_layer_as_layer_interface")
 }
 
 // Copyright (c) 2023 Ubique Innovation AG <https://www.ubique.ch>
@@ -156,35 +198,10 @@ include_cpp! {
 unsafe impl Send for TaskInterface {}
 unsafe impl Sync for TaskInterface {}
 
-pub trait TaskSpawner {
-    fn spawn_blocking(&self, task: autocxx::cxx::SharedPtr<TaskInterface>);
-}
-
-pub struct DefaultSpawner {
-    rt: tokio::runtime::Runtime,
-}
-
-impl TaskSpawner for DefaultSpawner {
-    fn spawn_blocking(&self, task: autocxx::cxx::SharedPtr<TaskInterface>) {
-        self.rt.spawn_blocking(move || {
-            log::debug!("running: {}", get_id(task.clone()));
-            run_task(task.clone());
-            log::debug!("finished: {}", get_id(task));
-        });
-    }
-}
-
 type OptionalSender = Option<std::sync::mpsc::Sender<cxx::SharedPtr<TaskInterface>>>;
 type OptionalSpawner = Option<Box<dyn TaskSpawner + Send + Sync>>;
 type RuntimeType = (OptionalSender, OptionalSpawner);
 
-pub struct SchedulerInterfaceRust {
-    pub spawner: Box<dyn TaskSpawner>,
-    pub channel: Sender<autocxx::cxx::SharedPtr<TaskInterface>>,
-}
-use std::sync::mpsc::{Receiver, Sender};
-
-pub struct TaskReceiver(Receiver<autocxx::cxx::SharedPtr<TaskInterface>>);
 #[cxx::bridge]
 mod custom {
     extern "Rust" {
@@ -232,50 +249,6 @@ mod Tiled2dMapLayerConfigWrapperImplMod {
     impl Box<Tiled2dMapLayerConfigWrapperImpl> {}
 }
 
-impl SchedulerInterfaceRust {
-    pub fn new() -> (Self, Receiver<autocxx::cxx::SharedPtr<TaskInterface>>) {
-        let (sender, receiver) = std::sync::mpsc::channel();
-        (
-            Self {
-                spawner: Box::new(DefaultSpawner {
-                    rt: tokio::runtime::Builder::new_multi_thread()
-                        .enable_all()
-                        .max_blocking_threads(5)
-                        .worker_threads(1)
-                        .thread_keep_alive(std::time::Duration::from_secs(5))
-                        .build()
-                        .expect("Failed to build internal tasks runtime"),
-                }),
-                channel: sender,
-            },
-            receiver,
-        )
-    }
-    fn addTaskRust(&self, task: autocxx::cxx::SharedPtr<TaskInterface>) {
-        let t = task.clone();
-        if !is_graphics(t.clone()) {
-            self.spawner.spawn_blocking(task);
-        } else {
-            if self.channel.send(task).is_err() {
-                log::error!("Could not submit task");
-            }
-        }
-    }
-    fn removeTaskRust(&self, id: String) {
-        println!("remove {}", id);
-        log::debug!("removeTask")
-    }
-    fn clearRust(&self) {
-        log::debug!("clear")
-    }
-    fn pauseRust(&self) {
-        log::debug!("pause")
-    }
-    fn resumeRust(&self) {
-        log::debug!("resume")
-    }
-}
-
 pub struct LayerInfoInterfaceRust {
     identifier: String,
     coordinates: UniquePtr<CxxVector<Coord>>,
@@ -315,13 +288,19 @@ mod LayerInfoInterfaceMod {
 
 impl LayerInfoInterfaceRust {
     fn getIdentifier(&self) -> String {
-        todo! {}
+        self.identifier.clone()
     }
     fn getCoordinates(&self) -> UniquePtr<CxxVector<Coord>> {
-        todo! {}
+        match self.coordinates.as_ref() {
+            Some(coordinates) => copy_coords(coordinates),
+            None => make_vec_coord(),
+        }
     }
     fn getStyle(&self) -> UniquePtr<LineStyle> {
-        todo! {}
+        match self.style.as_ref() {
+            Some(style) => copy_line_style(style),
+            None => line::LineStyleOptions::default().to_line_style(),
+        }
     }
 }
 
@@ -362,5 +341,16 @@ mod LoaderInterfaceWrapperMod {
         type DataLoaderResult = super::DataLoaderResult;
 
     }
+    unsafe extern "C++" {
+        include!("LoaderInterfaceImpl.h");
+        include!("LoaderStatus.h");
+        type LoaderStatus = super::LoaderStatus;
+        fn make_data_loader_result(
+            data: &[u8],
+            status: LoaderStatus,
+            etag: &CxxString,
+            error_code: &CxxString,
+        ) -> UniquePtr<DataLoaderResult>;
+    }
     impl Box<LoaderInterfaceWrapperImpl> {}
 }
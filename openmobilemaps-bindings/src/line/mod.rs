@@ -0,0 +1,238 @@
+// Copyright (c) 2023 Ubique Innovation AG <https://www.ubique.ch>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::HashMap;
+
+use anyhow::bail;
+use cxx::{SharedPtr, UniquePtr};
+
+use crate::*;
+
+/// Unit of [`LineStyleOptions::width`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WidthType {
+    /// The line keeps its width on screen when zooming.
+    #[default]
+    ScreenPixel,
+    /// The width is given in units of the map's coordinate system.
+    MapUnit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineCap {
+    Butt,
+    #[default]
+    Round,
+    Square,
+}
+
+/// Number of dash and gap lengths the line shaders support.
+pub const MAX_DASH_VALUES: usize = 4;
+
+/// How a line of a [`LineLayer`] is drawn. Colors are RGBA with components from 0 to 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineStyleOptions {
+    pub color: [f32; 4],
+    /// Color of the gaps of a dashed line.
+    pub gap_color: [f32; 4],
+    pub opacity: f32,
+    pub width: f32,
+    pub width_type: WidthType,
+    /// Alternating dash and gap lengths in multiples of the line width, empty for a solid
+    /// line. Like in SVG an odd number of values is repeated, the repeated pattern may have
+    /// at most [`MAX_DASH_VALUES`] values.
+    pub dash: Vec<f32>,
+    pub cap: LineCap,
+}
+
+impl Default for LineStyleOptions {
+    fn default() -> Self {
+        Self {
+            color: [0.0, 0.0, 0.0, 1.0],
+            gap_color: [0.0, 0.0, 0.0, 0.0],
+            opacity: 1.0,
+            width: 2.0,
+            width_type: WidthType::ScreenPixel,
+            dash: vec![],
+            cap: LineCap::Round,
+        }
+    }
+}
+
+impl LineStyleOptions {
+    /// Dashes of `dash` line widths with gaps of `gap` line widths.
+    pub fn dashed(mut self, dash: f32, gap: f32) -> Self {
+        self.dash = vec![dash, gap];
+        self
+    }
+
+    /// Square dots spaced by `gap` line widths, e.g. for walking sections.
+    pub fn dotted(mut self, gap: f32) -> Self {
+        // the shaders only round the ends of a line, not of its dashes, so every dot is a
+        // square as long as the line is wide
+        self.dash = vec![1.0, gap];
+        self
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let pattern_len = self.dash.len() * (1 + self.dash.len() % 2);
+        if pattern_len > MAX_DASH_VALUES {
+            bail!(
+                "At most {MAX_DASH_VALUES} dash values are supported, {:?} has {pattern_len}",
+                self.dash
+            );
+        }
+        if self.dash.iter().any(|value| !value.is_finite() || *value < 0.0) {
+            bail!("Invalid dash values {:?}", self.dash);
+        }
+        if !self.width.is_finite() || self.width <= 0.0 {
+            bail!("Invalid line width {}", self.width);
+        }
+        Ok(())
+    }
+
+    pub fn to_line_style(&self) -> UniquePtr<LineStyle> {
+        let color = |[r, g, b, a]: [f32; 4]| Color::new(r, g, b, a).within_unique_ptr();
+        let width_type = match self.width_type {
+            WidthType::ScreenPixel => SizeType::SCREEN_PIXEL,
+            WidthType::MapUnit => SizeType::MAP_UNIT,
+        };
+        let cap = match self.cap {
+            LineCap::Butt => LineCapType::BUTT,
+            LineCap::Round => LineCapType::ROUND,
+            LineCap::Square => LineCapType::SQUARE,
+        };
+        // starts out empty, which draws a solid line
+        let mut dash = make_default_dash();
+        for value in &self.dash {
+            add_float(dash.pin_mut(), *value);
+        }
+        make_line_style(
+            &color(self.color),
+            &color(self.gap_color),
+            self.opacity,
+            width_type,
+            self.width,
+            &dash,
+            cap,
+        )
+    }
+}
+
+/// A layer of polylines that are added, replaced and removed by identifier.
+pub struct LineLayer {
+    layer: SharedPtr<LineLayerInterface>,
+    lines: HashMap<String, SharedPtr<LineInfoInterface>>,
+}
+
+impl LineLayer {
+    pub fn new() -> anyhow::Result<Self> {
+        let layer = LineLayerInterface::create();
+        if layer.is_null() {
+            bail!("Failed to create line layer");
+        }
+        Ok(Self {
+            layer,
+            lines: HashMap::new(),
+        })
+    }
+
+    /// The layer to add to the map.
+    pub fn layer_interface(&self) -> SharedPtr<LayerInterface> {
+        line_layer_as_layer_interface(self.layer.clone())
+    }
+
+    /// Adds a line, or replaces the line with the same `identifier`.
+    pub fn add_line(
+        &mut self,
+        identifier: &str,
+        coordinates: &[Coordinate],
+        style: &LineStyleOptions,
+    ) -> anyhow::Result<()> {
+        if coordinates.len() < 2 {
+            bail!("Line {identifier} needs at least two coordinates");
+        }
+        style.validate()?;
+        let mut builder = LineInfoInterfaceWrapperBuilder::new().within_unique_ptr();
+        if builder.is_null() {
+            bail!("Failed to initialize line builder");
+        }
+        for coordinate in coordinates {
+            builder.pin_mut().addCoordinate(coordinate.to_coord().pin_mut());
+        }
+        builder.pin_mut().setStyle(style.to_line_style());
+        builder.pin_mut().setIdentifier(identifier);
+        let line = builder.pin_mut().build();
+        if line.is_null() {
+            bail!("Failed to build line {identifier}");
+        }
+
+        self.remove_line(identifier);
+        let layer = &self.layer;
+        pin_mut!(layer).add(&line);
+        self.lines.insert(identifier.to_owned(), line);
+        Ok(())
+    }
+
+    /// Same as [`LineLayer::add_line`], to make replacing a line read as such.
+    pub fn update_line(
+        &mut self,
+        identifier: &str,
+        coordinates: &[Coordinate],
+        style: &LineStyleOptions,
+    ) -> anyhow::Result<()> {
+        self.add_line(identifier, coordinates, style)
+    }
+
+    /// Removes the line with `identifier`, returns whether there was one.
+    pub fn remove_line(&mut self, identifier: &str) -> bool {
+        let Some(line) = self.lines.remove(identifier) else {
+            return false;
+        };
+        let layer = &self.layer;
+        pin_mut!(layer).remove(&line);
+        true
+    }
+
+    pub fn clear(&mut self) {
+        let layer = &self.layer;
+        pin_mut!(layer).clear();
+        self.lines.clear();
+    }
+
+    pub fn identifiers(&self) -> impl Iterator<Item = &str> {
+        self.lines.keys().map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_dash_is_solid() {
+        assert!(make_default_dash().is_empty());
+        assert!(LineStyleOptions::default().dash.is_empty());
+    }
+
+    #[test]
+    fn validates_dash_patterns() {
+        let style = LineStyleOptions::default();
+        assert!(style.clone().dashed(2.0, 1.0).validate().is_ok());
+        assert!(style.clone().dotted(2.0).validate().is_ok());
+        // an odd pattern is repeated to six values
+        let odd = LineStyleOptions {
+            dash: vec![1.0, 2.0, 3.0],
+            ..style.clone()
+        };
+        assert!(odd.validate().is_err());
+        let negative = LineStyleOptions {
+            dash: vec![1.0, -1.0],
+            ..style
+        };
+        assert!(negative.validate().is_err());
+    }
+}
@@ -0,0 +1,282 @@
+// Copyright (c) 2023 Ubique Innovation AG <https://www.ubique.ch>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use anyhow::{bail, Context};
+use cxx::{SharedPtr, UniquePtr};
+use rusqlite::{OpenFlags, OptionalExtension};
+
+use crate::bindings::impls::{data_result, empty_texture_result, texture_result, LoadFailure};
+use crate::cache::DecodedTileCache;
+use crate::raster::{
+    create_raster_layer, decoded_texture_result, default_zoom_info, lon_lat_to_web_mercator,
+    parse_tile_url, TileMatrixSet,
+};
+use crate::*;
+
+/// Values of the MBTiles `metadata` table the layer depends on.
+#[derive(Debug, Clone)]
+pub struct MbTilesMetadata {
+    pub name: String,
+    pub format: Option<String>,
+    pub min_zoom: i32,
+    pub max_zoom: i32,
+    /// `left, bottom, right, top` in WGS84.
+    pub bounds: Option<[f64; 4]>,
+}
+
+/// A read-only MBTiles file, shared by the layer config and the loader.
+pub struct MbTiles {
+    /// Canonical path of the file, identifies its tiles in the decoded tile cache.
+    path: PathBuf,
+    connection: Mutex<rusqlite::Connection>,
+    metadata: MbTilesMetadata,
+    /// The web mercator pyramid limited to the tiles within the bounds of the file.
+    matrix_set: TileMatrixSet,
+}
+
+impl MbTiles {
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Arc<Self>> {
+        let path = path.as_ref();
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("Failed to open MBTiles file {}", path.display()))?;
+        let connection = rusqlite::Connection::open_with_flags(
+            &path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+        .with_context(|| format!("Failed to open MBTiles file {}", path.display()))?;
+        let metadata = Self::read_metadata(&connection, &path)?;
+        log::debug!("Opened {}: {metadata:?}", path.display());
+        let mut matrix_set =
+            TileMatrixSet::web_mercator_quad(metadata.min_zoom..=metadata.max_zoom, 256);
+        if let Some([left, bottom, right, top]) = metadata.bounds {
+            if left < right && bottom < top {
+                let (min_x, min_y) = lon_lat_to_web_mercator(left, bottom);
+                let (max_x, max_y) = lon_lat_to_web_mercator(right, top);
+                matrix_set = matrix_set.limited_to([min_x, min_y, max_x, max_y]);
+            }
+        }
+        Ok(Arc::new(Self {
+            path,
+            connection: Mutex::new(connection),
+            metadata,
+            matrix_set,
+        }))
+    }
+
+    fn read_metadata(
+        connection: &rusqlite::Connection,
+        path: &Path,
+    ) -> anyhow::Result<MbTilesMetadata> {
+        let value = |name: &str| -> anyhow::Result<Option<String>> {
+            connection
+                .query_row("SELECT value FROM metadata WHERE name = ?1", [name], |row| {
+                    row.get(0)
+                })
+                .optional()
+                .with_context(|| format!("Failed to read metadata {name}"))
+        };
+
+        let name = value("name")?.unwrap_or_else(|| {
+            path.file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        });
+        let format = value("format")?;
+
+        // minzoom and maxzoom are optional, fall back to what the tiles table holds
+        let min_zoom = match value("minzoom")?.and_then(|v| v.trim().parse().ok()) {
+            Some(zoom) => zoom,
+            None => connection
+                .query_row("SELECT MIN(zoom_level) FROM tiles", [], |row| {
+                    row.get::<_, Option<i32>>(0)
+                })
+                .context("Failed to read zoom levels")?
+                .unwrap_or(0),
+        };
+        let max_zoom = match value("maxzoom")?.and_then(|v| v.trim().parse().ok()) {
+            Some(zoom) => zoom,
+            None => connection
+                .query_row("SELECT MAX(zoom_level) FROM tiles", [], |row| {
+                    row.get::<_, Option<i32>>(0)
+                })
+                .context("Failed to read zoom levels")?
+                .unwrap_or(min_zoom),
+        };
+        if !(0..=30).contains(&min_zoom) || !(min_zoom..=30).contains(&max_zoom) {
+            bail!("Invalid zoom range {min_zoom}..={max_zoom} in {}", path.display());
+        }
+
+        let bounds = value("bounds")?.and_then(|bounds| {
+            let values: Vec<f64> = bounds
+                .split(',')
+                .filter_map(|v| v.trim().parse().ok())
+                .collect();
+            <[f64; 4]>::try_from(values).ok()
+        });
+
+        Ok(MbTilesMetadata {
+            name,
+            format,
+            min_zoom,
+            max_zoom,
+            bounds,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn metadata(&self) -> &MbTilesMetadata {
+        &self.metadata
+    }
+
+    /// The area covered by the tiles in EPSG:3857, e.g. to pass to the renderer as bounds.
+    pub fn bounds(&self) -> Option<UniquePtr<RectCoord>> {
+        let [left, bottom, right, top] = self.metadata.bounds?;
+        let (min_x, min_y) = lon_lat_to_web_mercator(left, bottom);
+        let (max_x, max_y) = lon_lat_to_web_mercator(right, top);
+        Some(
+            RectCoord::new(
+                Coord::new(CoordinateSystemIdentifiers::EPSG3857(), min_x, max_y, 0.0)
+                    .within_unique_ptr(),
+                Coord::new(CoordinateSystemIdentifiers::EPSG3857(), max_x, min_y, 0.0)
+                    .within_unique_ptr(),
+            )
+            .within_unique_ptr(),
+        )
+    }
+
+    /// Reads the tile in XYZ scheme, MBTiles stores rows in TMS scheme (y pointing north).
+    pub fn tile(&self, zoom: i32, x: i32, y: i32) -> anyhow::Result<Option<Vec<u8>>> {
+        if !(0..=30).contains(&zoom) {
+            bail!("Invalid zoom level {zoom}");
+        }
+        let tms_y = (1 << zoom) - 1 - y;
+        let connection = self
+            .connection
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        connection
+            .query_row(
+                "SELECT tile_data FROM tiles WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3",
+                [zoom, x, tms_y],
+                |row| row.get(0),
+            )
+            .optional()
+            .with_context(|| format!("Failed to read tile {zoom}/{x}/{y}"))
+    }
+
+    /// The URL of a tile given in the columns and rows maps-core counts from the bounds.
+    fn tile_url(&self, zoom: i32, x: i32, y: i32) -> String {
+        let (first_column, first_row) = self.matrix_set.tile_offset(zoom);
+        let (x, y) = (x + first_column, y + first_row);
+        format!("mbtiles://{}/{zoom}/{x}/{y}", self.path.display())
+    }
+}
+
+/// Layer config for the tiles of an [`MbTiles`] file.
+pub struct MbTilesConfig(pub Arc<MbTiles>);
+
+impl Tiled2dMapLayerConfigTrait for MbTilesConfig {
+    fn getCoordinateSystemIdentifier(&self) -> UniquePtr<cxx::CxxString> {
+        CoordinateSystemIdentifiers::EPSG3857()
+    }
+
+    fn getTileUrl(&self, x: i32, y: i32, _t: i32, zoom: i32) -> UniquePtr<cxx::CxxString> {
+        make_string(&self.0.tile_url(zoom, x, y))
+    }
+
+    fn getZoomLevelInfos(&self) -> UniquePtr<CxxVector<Tiled2dMapZoomLevelInfo>> {
+        self.0.matrix_set.zoom_level_infos()
+    }
+
+    fn getZoomInfo(&self) -> UniquePtr<Tiled2dMapZoomInfo> {
+        default_zoom_info()
+    }
+
+    fn getLayerName(&self) -> UniquePtr<cxx::CxxString> {
+        make_string(&self.0.metadata().name)
+    }
+}
+
+/// Loader serving the tiles of an [`MbTiles`] file, without any network access.
+pub struct MbTilesLoader {
+    mbtiles: Arc<MbTiles>,
+    decoded: Arc<DecodedTileCache>,
+}
+
+impl MbTilesLoader {
+    pub fn new(mbtiles: Arc<MbTiles>) -> Self {
+        Self {
+            mbtiles,
+            decoded: DecodedTileCache::shared(),
+        }
+    }
+
+    fn read(&self, url: &str) -> Result<Vec<u8>, LoadFailure> {
+        let Some((zoom, x, y)) = parse_tile_url(url, "mbtiles") else {
+            return Err(LoadFailure::new(url, LoaderStatus::ERROR_OTHER, "Not an MBTiles URL"));
+        };
+        match self.mbtiles.tile(zoom, x, y) {
+            Ok(Some(data)) => Ok(data),
+            Ok(None) => Err(LoadFailure::new(url, LoaderStatus::ERROR_404, "Tile not in MBTiles file")),
+            Err(e) => Err(LoadFailure::new(url, LoaderStatus::ERROR_OTHER, format!("{e:#}"))),
+        }
+    }
+}
+
+impl LoaderInterfaceTrait for MbTilesLoader {
+    fn loadTextureWrapper(
+        &self,
+        url: &cxx::CxxString,
+        _etag: cxx::UniquePtr<cxx::CxxString>,
+    ) -> cxx::UniquePtr<TextureLoaderResult> {
+        let Ok(url) = url.to_str() else {
+            log::warn!("Tile URL is not valid UTF-8: {url:?}");
+            return empty_texture_result(LoaderStatus::ERROR_OTHER, "URL is not valid UTF-8");
+        };
+        if let Some(tile) = self.decoded.get(url) {
+            return texture_result(tile);
+        }
+        let data = match self.read(url) {
+            Ok(data) => data,
+            Err(failure) => return empty_texture_result(failure.status, &failure.error_code),
+        };
+        decoded_texture_result(&self.decoded, url, &data, None)
+    }
+
+    fn loadDataWrapper(
+        &self,
+        url: &cxx::CxxString,
+        _etag: cxx::UniquePtr<cxx::CxxString>,
+    ) -> cxx::UniquePtr<DataLoaderResult> {
+        let Ok(url) = url.to_str() else {
+            log::warn!("Data URL is not valid UTF-8: {url:?}");
+            return data_result(&[], LoaderStatus::ERROR_OTHER, None, "URL is not valid UTF-8");
+        };
+        match self.read(url) {
+            Ok(data) => data_result(&data, LoaderStatus::OK, None, ""),
+            Err(failure) => data_result(&[], failure.status, None, &failure.error_code),
+        }
+    }
+}
+
+/// Builds a raster layer showing the tiles of `mbtiles`.
+pub fn create_mbtiles_raster_layer(
+    mbtiles: Arc<MbTiles>,
+) -> anyhow::Result<(SharedPtr<LoaderInterfaceImpl>, SharedPtr<LayerInterface>)> {
+    create_raster_layer(
+        Box::new(MbTilesConfig(mbtiles.clone())),
+        Box::new(MbTilesLoader::new(mbtiles)),
+    )
+}
@@ -0,0 +1,845 @@
+// Copyright (c) 2023 Ubique Innovation AG <https://www.ubique.ch>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use anyhow::{bail, Context};
+use cxx::{SharedPtr, UniquePtr};
+
+use crate::bindings::impls::{data_result, empty_texture_result, texture_result, LoadFailure};
+use crate::cache::DecodedTileCache;
+use crate::raster::{
+    create_raster_layer, decoded_texture_result, default_zoom_info, lon_lat_to_web_mercator,
+    parse_tile_url, TileMatrixSet,
+};
+use crate::*;
+
+const HEADER_LENGTH: u64 = 127;
+/// Root directory plus up to three levels of leaf directories, as allowed by the spec.
+const MAX_DIRECTORY_DEPTH: usize = 4;
+const MAX_CACHED_LEAVES: usize = 64;
+
+/// Random access to the bytes of an archive.
+pub trait RangeReader: Send + Sync {
+    fn read_range(&self, offset: u64, length: u64) -> anyhow::Result<Vec<u8>>;
+}
+
+/// Reads an archive from a local file.
+pub struct FileRangeReader(Mutex<File>);
+
+impl FileRangeReader {
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open PMTiles file {}", path.display()))?;
+        Ok(Self(Mutex::new(file)))
+    }
+}
+
+impl RangeReader for FileRangeReader {
+    fn read_range(&self, offset: u64, length: u64) -> anyhow::Result<Vec<u8>> {
+        let mut file = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        // the ranges come from the archive, check them before allocating the buffer
+        let file_length = file.metadata()?.len();
+        if offset
+            .checked_add(length)
+            .map_or(true, |end| end > file_length)
+        {
+            bail!("Range of {length} bytes at {offset} is outside of the {file_length} byte file");
+        }
+        file.seek(SeekFrom::Start(offset))?;
+        let mut buffer = vec![0; length as usize];
+        file.read_exact(&mut buffer)
+            .with_context(|| format!("Failed to read {length} bytes at {offset}"))?;
+        Ok(buffer)
+    }
+}
+
+/// Reads an archive over HTTP with range requests.
+pub struct HttpRangeReader {
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl HttpRangeReader {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            agent: ureq::Agent::new(),
+        }
+    }
+}
+
+impl RangeReader for HttpRangeReader {
+    fn read_range(&self, offset: u64, length: u64) -> anyhow::Result<Vec<u8>> {
+        if length == 0 {
+            return Ok(vec![]);
+        }
+        // the ranges come from the archive and may be anything
+        let Some(end) = offset.checked_add(length - 1) else {
+            bail!("Range of {length} bytes at {offset} is out of bounds");
+        };
+        let response = self
+            .agent
+            .get(&self.url)
+            .set("Range", &format!("bytes={offset}-{end}"))
+            .call()
+            .with_context(|| format!("Failed to request bytes {offset}-{end} of {}", self.url))?;
+        if response.status() != 206 {
+            bail!("{} does not support range requests", self.url);
+        }
+        // grows with the response instead of trusting the length read from the archive
+        let mut buffer = vec![];
+        response
+            .into_reader()
+            .take(length)
+            .read_to_end(&mut buffer)
+            .context("Failed to read range response")?;
+        if buffer.len() as u64 != length {
+            bail!("Expected {length} bytes, got {}", buffer.len());
+        }
+        Ok(buffer)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Unknown,
+    None,
+    Gzip,
+    Brotli,
+    Zstd,
+}
+
+impl Compression {
+    fn from_byte(byte: u8) -> Self {
+        match byte {
+            1 => Compression::None,
+            2 => Compression::Gzip,
+            3 => Compression::Brotli,
+            4 => Compression::Zstd,
+            _ => Compression::Unknown,
+        }
+    }
+
+    fn is_supported(self) -> bool {
+        matches!(self, Compression::None | Compression::Gzip)
+    }
+
+    fn decompress(self, data: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data),
+            Compression::Gzip => {
+                let mut decompressed = vec![];
+                flate2::read::GzDecoder::new(data.as_slice())
+                    .read_to_end(&mut decompressed)
+                    .context("Failed to decompress gzip data")?;
+                Ok(decompressed)
+            }
+            other => bail!("Unsupported compression {other:?}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileType {
+    Unknown,
+    Mvt,
+    Png,
+    Jpeg,
+    Webp,
+    Avif,
+}
+
+impl TileType {
+    fn from_byte(byte: u8) -> Self {
+        match byte {
+            1 => TileType::Mvt,
+            2 => TileType::Png,
+            3 => TileType::Jpeg,
+            4 => TileType::Webp,
+            5 => TileType::Avif,
+            _ => TileType::Unknown,
+        }
+    }
+
+    /// Image format of raster tiles, `None` if it has to be guessed from the data.
+    pub fn image_format(self) -> Option<image::ImageFormat> {
+        match self {
+            TileType::Png => Some(image::ImageFormat::Png),
+            TileType::Jpeg => Some(image::ImageFormat::Jpeg),
+            TileType::Webp => Some(image::ImageFormat::WebP),
+            TileType::Avif => Some(image::ImageFormat::Avif),
+            TileType::Mvt | TileType::Unknown => None,
+        }
+    }
+}
+
+/// The fixed size header at the start of every PMTiles v3 archive.
+#[derive(Debug, Clone)]
+pub struct PmTilesHeader {
+    pub root_directory: (u64, u64),
+    pub metadata: (u64, u64),
+    pub leaf_directories: (u64, u64),
+    pub tile_data: (u64, u64),
+    pub internal_compression: Compression,
+    pub tile_compression: Compression,
+    pub tile_type: TileType,
+    pub min_zoom: u8,
+    pub max_zoom: u8,
+    /// `left, bottom, right, top` in WGS84.
+    pub bounds: [f64; 4],
+    pub center_zoom: u8,
+    pub center: (f64, f64),
+}
+
+impl PmTilesHeader {
+    fn parse(bytes: &[u8]) -> anyhow::Result<Self> {
+        if bytes.len() < HEADER_LENGTH as usize || &bytes[0..7] != b"PMTiles" {
+            bail!("Not a PMTiles archive");
+        }
+        if bytes[7] != 3 {
+            bail!("Unsupported PMTiles version {}", bytes[7]);
+        }
+        let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        let e7_at = |at: usize| {
+            i32::from_le_bytes(bytes[at..at + 4].try_into().unwrap()) as f64 / 10_000_000.0
+        };
+        Ok(Self {
+            root_directory: (u64_at(8), u64_at(16)),
+            metadata: (u64_at(24), u64_at(32)),
+            leaf_directories: (u64_at(40), u64_at(48)),
+            tile_data: (u64_at(56), u64_at(64)),
+            internal_compression: Compression::from_byte(bytes[97]),
+            tile_compression: Compression::from_byte(bytes[98]),
+            tile_type: TileType::from_byte(bytes[99]),
+            min_zoom: bytes[100],
+            max_zoom: bytes[101],
+            bounds: [e7_at(102), e7_at(106), e7_at(110), e7_at(114)],
+            center_zoom: bytes[118],
+            center: (e7_at(119), e7_at(123)),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    tile_id: u64,
+    offset: u64,
+    length: u64,
+    /// Zero for entries pointing to a leaf directory.
+    run_length: u64,
+}
+
+fn read_varint(bytes: &mut &[u8]) -> anyhow::Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let Some((&byte, rest)) = bytes.split_first() else {
+            bail!("Truncated varint in directory");
+        };
+        *bytes = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    bail!("Varint in directory is too long")
+}
+
+fn parse_directory(mut bytes: &[u8]) -> anyhow::Result<Vec<Entry>> {
+    let bytes = &mut bytes;
+    let count = read_varint(bytes)? as usize;
+    // every entry takes at least four bytes
+    if count > bytes.len() / 4 {
+        bail!(
+            "Directory claims {count} entries but is only {} bytes",
+            bytes.len()
+        );
+    }
+    let mut entries = vec![
+        Entry {
+            tile_id: 0,
+            offset: 0,
+            length: 0,
+            run_length: 0,
+        };
+        count
+    ];
+
+    let mut last_id = 0;
+    for entry in entries.iter_mut() {
+        last_id = read_varint(bytes)?
+            .checked_add(last_id)
+            .context("Tile id in directory is out of bounds")?;
+        entry.tile_id = last_id;
+    }
+    for entry in entries.iter_mut() {
+        entry.run_length = read_varint(bytes)?;
+    }
+    for entry in entries.iter_mut() {
+        entry.length = read_varint(bytes)?;
+    }
+    for i in 0..count {
+        let value = read_varint(bytes)?;
+        entries[i].offset = if value == 0 && i > 0 {
+            // directly follows the previous entry
+            let previous = entries[i - 1];
+            previous
+                .offset
+                .checked_add(previous.length)
+                .context("Offset in directory is out of bounds")?
+        } else {
+            value.saturating_sub(1)
+        };
+    }
+    Ok(entries)
+}
+
+/// Position of a tile on the Hilbert curve over all zoom levels, as used for tile ids.
+pub fn tile_id(zoom: u8, x: u64, y: u64) -> u64 {
+    // number of tiles on all lower zoom levels
+    let mut id = ((1u64 << (2 * zoom as u32)) - 1) / 3;
+    let (mut x, mut y) = (x, y);
+    let mut s = (1u64 << zoom) >> 1;
+    while s > 0 {
+        let rx = u64::from(x & s > 0);
+        let ry = u64::from(y & s > 0);
+        id += s * s * ((3 * rx) ^ ry);
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - (x & (s - 1));
+                y = s - 1 - (y & (s - 1));
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s >>= 1;
+    }
+    id
+}
+
+/// A PMTiles v3 archive read through a [`RangeReader`].
+pub struct PmTiles {
+    /// Canonical path or URL of the archive, identifies its tiles in the decoded tile cache.
+    source: String,
+    name: String,
+    reader: Box<dyn RangeReader>,
+    header: PmTilesHeader,
+    /// The web mercator pyramid limited to the tiles within the bounds of the archive.
+    matrix_set: TileMatrixSet,
+    root: Vec<Entry>,
+    leaves: Mutex<HashMap<(u64, u64), Arc<Vec<Entry>>>>,
+}
+
+impl PmTiles {
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Arc<Self>> {
+        let path = path.as_ref();
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("Failed to open PMTiles file {}", path.display()))?;
+        let reader = FileRangeReader::open(&path)?;
+        Self::from_reader(path.display().to_string(), Box::new(reader))
+    }
+
+    /// Reads the archive at `source`, which has to identify it uniquely, e.g. its canonical
+    /// path or its URL.
+    pub fn from_reader(
+        source: impl Into<String>,
+        reader: Box<dyn RangeReader>,
+    ) -> anyhow::Result<Arc<Self>> {
+        let source = source.into();
+        let name = Path::new(&source)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| source.clone());
+        let header = PmTilesHeader::parse(&reader.read_range(0, HEADER_LENGTH)?)
+            .with_context(|| format!("Failed to read header of {name}"))?;
+        for (kind, compression) in [
+            ("directory", header.internal_compression),
+            ("tile", header.tile_compression),
+        ] {
+            if !compression.is_supported() {
+                bail!(
+                    "{name} uses {compression:?} {kind} compression, only uncompressed and gzip \
+                     compressed archives are supported"
+                );
+            }
+        }
+        let (offset, length) = header.root_directory;
+        let root = header
+            .internal_compression
+            .decompress(reader.read_range(offset, length)?)
+            .and_then(|bytes| parse_directory(&bytes))
+            .with_context(|| format!("Failed to read root directory of {name}"))?;
+        log::debug!("Opened {name}: {header:?}");
+        let mut matrix_set =
+            TileMatrixSet::web_mercator_quad(header.min_zoom as i32..=header.max_zoom as i32, 256);
+        let [left, bottom, right, top] = header.bounds;
+        if left < right && bottom < top {
+            let (min_x, min_y) = lon_lat_to_web_mercator(left, bottom);
+            let (max_x, max_y) = lon_lat_to_web_mercator(right, top);
+            matrix_set = matrix_set.limited_to([min_x, min_y, max_x, max_y]);
+        }
+        Ok(Arc::new(Self {
+            source,
+            name,
+            reader,
+            header,
+            matrix_set,
+            root,
+            leaves: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn header(&self) -> &PmTilesHeader {
+        &self.header
+    }
+
+    /// The area covered by the tiles in EPSG:3857, e.g. to pass to the renderer as bounds.
+    pub fn bounds(&self) -> UniquePtr<RectCoord> {
+        let [left, bottom, right, top] = self.header.bounds;
+        let (min_x, min_y) = lon_lat_to_web_mercator(left, bottom);
+        let (max_x, max_y) = lon_lat_to_web_mercator(right, top);
+        RectCoord::new(
+            Coord::new(CoordinateSystemIdentifiers::EPSG3857(), min_x, max_y, 0.0)
+                .within_unique_ptr(),
+            Coord::new(CoordinateSystemIdentifiers::EPSG3857(), max_x, min_y, 0.0)
+                .within_unique_ptr(),
+        )
+        .within_unique_ptr()
+    }
+
+    fn lock_leaves(&self) -> MutexGuard<'_, HashMap<(u64, u64), Arc<Vec<Entry>>>> {
+        self.leaves.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn leaf(&self, offset: u64, length: u64) -> anyhow::Result<Arc<Vec<Entry>>> {
+        let key = (offset, length);
+        if let Some(leaf) = self.lock_leaves().get(&key) {
+            return Ok(leaf.clone());
+        }
+        let start = offset_in(
+            self.header.leaf_directories,
+            offset,
+            length,
+            "leaf directories",
+        )?;
+        let bytes = self.reader.read_range(start, length)?;
+        let leaf = Arc::new(parse_directory(
+            &self.header.internal_compression.decompress(bytes)?,
+        )?);
+        let mut leaves = self.lock_leaves();
+        if leaves.len() >= MAX_CACHED_LEAVES {
+            leaves.clear();
+        }
+        leaves.insert(key, leaf.clone());
+        Ok(leaf)
+    }
+
+    /// Reads the decompressed tile in XYZ scheme, `None` if the archive does not contain it.
+    pub fn tile(&self, zoom: i32, x: i32, y: i32) -> anyhow::Result<Option<Vec<u8>>> {
+        if !(0..=30).contains(&zoom) || x < 0 || y < 0 || x >= 1 << zoom || y >= 1 << zoom {
+            bail!("Invalid tile {zoom}/{x}/{y}");
+        }
+        let id = tile_id(zoom as u8, x as u64, y as u64);
+
+        let mut directory = None::<Arc<Vec<Entry>>>;
+        for _ in 0..MAX_DIRECTORY_DEPTH {
+            let entries = directory
+                .as_deref()
+                .map(Vec::as_slice)
+                .unwrap_or(&self.root[..]);
+            // last entry with a tile id not larger than the one we look for
+            let index = entries.partition_point(|entry| entry.tile_id <= id);
+            let Some(entry) = index.checked_sub(1).map(|index| entries[index]) else {
+                return Ok(None);
+            };
+            if entry.run_length == 0 {
+                directory = Some(self.leaf(entry.offset, entry.length)?);
+                continue;
+            }
+            if id >= entry.tile_id.saturating_add(entry.run_length) {
+                return Ok(None);
+            }
+            let start = offset_in(
+                self.header.tile_data,
+                entry.offset,
+                entry.length,
+                "tile data",
+            )?;
+            let bytes = self.reader.read_range(start, entry.length)?;
+            return self.header.tile_compression.decompress(bytes).map(Some);
+        }
+        bail!("Directories of {} are nested too deep", self.name)
+    }
+
+    /// The URL of a tile given in the columns and rows maps-core counts from the bounds.
+    fn tile_url(&self, zoom: i32, x: i32, y: i32) -> String {
+        let (first_column, first_row) = self.matrix_set.tile_offset(zoom);
+        let (x, y) = (x + first_column, y + first_row);
+        format!("pmtiles://{}/{zoom}/{x}/{y}", self.source)
+    }
+}
+
+/// Position in the archive of `length` bytes at `offset` within `section`, given as offset
+/// and length in the archive. Fails unless the bytes lie within the section.
+fn offset_in(
+    section: (u64, u64),
+    offset: u64,
+    length: u64,
+    section_name: &str,
+) -> anyhow::Result<u64> {
+    let (section_offset, section_length) = section;
+    if offset
+        .checked_add(length)
+        .map_or(true, |end| end > section_length)
+    {
+        bail!("Range of {length} bytes at {offset} is outside of the {section_length} bytes of {section_name}");
+    }
+    section_offset
+        .checked_add(offset)
+        .with_context(|| format!("Offset {offset} into {section_name} is out of bounds"))
+}
+
+/// Layer config for the tiles of a [`PmTiles`] archive, taken from its header.
+pub struct PmTilesConfig(pub Arc<PmTiles>);
+
+impl Tiled2dMapLayerConfigTrait for PmTilesConfig {
+    fn getCoordinateSystemIdentifier(&self) -> UniquePtr<cxx::CxxString> {
+        CoordinateSystemIdentifiers::EPSG3857()
+    }
+
+    fn getTileUrl(&self, x: i32, y: i32, _t: i32, zoom: i32) -> UniquePtr<cxx::CxxString> {
+        make_string(&self.0.tile_url(zoom, x, y))
+    }
+
+    fn getZoomLevelInfos(&self) -> UniquePtr<CxxVector<Tiled2dMapZoomLevelInfo>> {
+        self.0.matrix_set.zoom_level_infos()
+    }
+
+    fn getZoomInfo(&self) -> UniquePtr<Tiled2dMapZoomInfo> {
+        default_zoom_info()
+    }
+
+    fn getLayerName(&self) -> UniquePtr<cxx::CxxString> {
+        make_string(self.0.name())
+    }
+}
+
+/// Loader serving the tiles of a [`PmTiles`] archive.
+pub struct PmTilesLoader {
+    pmtiles: Arc<PmTiles>,
+    decoded: Arc<DecodedTileCache>,
+}
+
+impl PmTilesLoader {
+    pub fn new(pmtiles: Arc<PmTiles>) -> Self {
+        Self {
+            pmtiles,
+            decoded: DecodedTileCache::shared(),
+        }
+    }
+
+    fn read(&self, url: &str) -> Result<Vec<u8>, LoadFailure> {
+        let Some((zoom, x, y)) = parse_tile_url(url, "pmtiles") else {
+            return Err(LoadFailure::new(
+                url,
+                LoaderStatus::ERROR_OTHER,
+                "Not a PMTiles URL",
+            ));
+        };
+        match self.pmtiles.tile(zoom, x, y) {
+            Ok(Some(data)) => Ok(data),
+            Ok(None) => Err(LoadFailure::new(
+                url,
+                LoaderStatus::ERROR_404,
+                "Tile not in PMTiles archive",
+            )),
+            Err(e) => Err(LoadFailure::new(
+                url,
+                LoaderStatus::ERROR_OTHER,
+                format!("{e:#}"),
+            )),
+        }
+    }
+}
+
+impl LoaderInterfaceTrait for PmTilesLoader {
+    fn loadTextureWrapper(
+        &self,
+        url: &cxx::CxxString,
+        _etag: cxx::UniquePtr<cxx::CxxString>,
+    ) -> cxx::UniquePtr<TextureLoaderResult> {
+        let Ok(url) = url.to_str() else {
+            log::warn!("Tile URL is not valid UTF-8: {url:?}");
+            return empty_texture_result(LoaderStatus::ERROR_OTHER, "URL is not valid UTF-8");
+        };
+        if let Some(tile) = self.decoded.get(url) {
+            return texture_result(tile);
+        }
+        let data = match self.read(url) {
+            Ok(data) => data,
+            Err(failure) => return empty_texture_result(failure.status, &failure.error_code),
+        };
+        let format = self.pmtiles.header().tile_type.image_format();
+        decoded_texture_result(&self.decoded, url, &data, format)
+    }
+
+    fn loadDataWrapper(
+        &self,
+        url: &cxx::CxxString,
+        _etag: cxx::UniquePtr<cxx::CxxString>,
+    ) -> cxx::UniquePtr<DataLoaderResult> {
+        let Ok(url) = url.to_str() else {
+            log::warn!("Data URL is not valid UTF-8: {url:?}");
+            return data_result(
+                &[],
+                LoaderStatus::ERROR_OTHER,
+                None,
+                "URL is not valid UTF-8",
+            );
+        };
+        match self.read(url) {
+            Ok(data) => data_result(&data, LoaderStatus::OK, None, ""),
+            Err(failure) => data_result(&[], failure.status, None, &failure.error_code),
+        }
+    }
+}
+
+/// Builds a raster layer showing the tiles of `pmtiles`.
+pub fn create_pmtiles_raster_layer(
+    pmtiles: Arc<PmTiles>,
+) -> anyhow::Result<(SharedPtr<LoaderInterfaceImpl>, SharedPtr<LayerInterface>)> {
+    let tile_type = pmtiles.header().tile_type;
+    if tile_type == TileType::Mvt {
+        bail!("{} contains vector tiles, not raster tiles", pmtiles.name());
+    }
+    create_raster_layer(
+        Box::new(PmTilesConfig(pmtiles.clone())),
+        Box::new(PmTilesLoader::new(pmtiles)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An archive held in memory, fails like a file for ranges past its end.
+    struct MemoryReader(Vec<u8>);
+
+    impl RangeReader for MemoryReader {
+        fn read_range(&self, offset: u64, length: u64) -> anyhow::Result<Vec<u8>> {
+            let end = offset.checked_add(length).context("Range overflows")?;
+            self.0
+                .get(offset as usize..end as usize)
+                .map(<[u8]>::to_vec)
+                .context("Range is outside of the archive")
+        }
+    }
+
+    fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            out.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    /// Encodes entries of `(tile_id, run_length, length, offset)` as the spec describes.
+    fn directory(entries: &[(u64, u64, u64, u64)]) -> Vec<u8> {
+        let mut out = vec![];
+        write_varint(&mut out, entries.len() as u64);
+        let mut last_id = 0;
+        for &(tile_id, ..) in entries {
+            write_varint(&mut out, tile_id - last_id);
+            last_id = tile_id;
+        }
+        for &(_, run_length, ..) in entries {
+            write_varint(&mut out, run_length);
+        }
+        for &(_, _, length, _) in entries {
+            write_varint(&mut out, length);
+        }
+        for (i, &(_, _, _, offset)) in entries.iter().enumerate() {
+            let follows = i > 0 && offset == entries[i - 1].3 + entries[i - 1].2;
+            write_varint(&mut out, if follows { 0 } else { offset + 1 });
+        }
+        out
+    }
+
+    /// An uncompressed PNG archive for zoom levels 0 to 2 covering the whole world.
+    fn archive(root: &[u8], leaves: &[u8], tiles: &[u8]) -> Vec<u8> {
+        let mut out = b"PMTiles\x03".to_vec();
+        let mut offset = HEADER_LENGTH;
+        for length in [root.len(), 0, leaves.len(), tiles.len()] {
+            out.extend(offset.to_le_bytes());
+            out.extend((length as u64).to_le_bytes());
+            offset += length as u64;
+        }
+        out.resize(97, 0);
+        out.extend([1, 1, 2, 0, 2]);
+        for value in [-180.0, -85.0, 180.0, 85.0] {
+            out.extend(((value * 10_000_000.0) as i32).to_le_bytes());
+        }
+        out.push(1);
+        out.extend([0i32, 0].map(i32::to_le_bytes).concat());
+        assert_eq!(out.len(), HEADER_LENGTH as usize);
+        out.extend([root, leaves, tiles].concat());
+        out
+    }
+
+    fn open(bytes: Vec<u8>) -> anyhow::Result<Arc<PmTiles>> {
+        PmTiles::from_reader("memory://test.pmtiles", Box::new(MemoryReader(bytes)))
+    }
+
+    /// Tile 0 in the root directory, the four tiles of zoom 1 in a run behind a leaf.
+    fn two_level_archive() -> Vec<u8> {
+        let leaf = directory(&[(1, 4, 3, 3)]);
+        let root = directory(&[(0, 1, 3, 0), (1, 0, leaf.len() as u64, 0)]);
+        archive(&root, &leaf, b"abcdef")
+    }
+
+    #[test]
+    fn computes_tile_ids_of_the_spec() {
+        let vectors = [
+            ((0, 0, 0), 0),
+            ((1, 0, 0), 1),
+            ((1, 0, 1), 2),
+            ((1, 1, 1), 3),
+            ((1, 1, 0), 4),
+            ((2, 0, 0), 5),
+            ((3, 0, 0), 21),
+            ((12, 3423, 1763), 19078479),
+        ];
+        for ((zoom, x, y), id) in vectors {
+            assert_eq!(tile_id(zoom, x, y), id, "{zoom}/{x}/{y}");
+        }
+    }
+
+    #[test]
+    fn reads_varints() {
+        let mut bytes: &[u8] = &[0x00, 0x7f, 0x96, 0x01, 0xff, 0xff, 0x03];
+        let values: Vec<u64> = (0..4).map(|_| read_varint(&mut bytes).unwrap()).collect();
+        assert_eq!(values, [0, 127, 150, 0xffff]);
+        assert!(bytes.is_empty());
+
+        assert!(read_varint(&mut &[0x80][..]).is_err());
+        assert!(read_varint(&mut &[0xff; 11][..]).is_err());
+    }
+
+    #[test]
+    fn parses_directories() {
+        let bytes = directory(&[
+            (0, 1, 10, 0),
+            (1, 3, 20, 10),
+            (5, 0, 30, 100),
+            (9, 2, 5, 30),
+        ]);
+        let entries = parse_directory(&bytes).unwrap();
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.tile_id, entry.run_length, entry.length, entry.offset))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (0, 1, 10, 0),
+                (1, 3, 20, 10),
+                (5, 0, 30, 100),
+                (9, 2, 5, 30)
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_directories() {
+        let bytes = directory(&[(0, 1, 10, 0), (1, 3, 20, 10)]);
+        assert!(parse_directory(&bytes[..bytes.len() - 1]).is_err());
+        // claims more entries than there are bytes
+        assert!(parse_directory(&[100, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn parses_headers() {
+        let bytes = two_level_archive();
+        let header = PmTilesHeader::parse(&bytes).unwrap();
+        assert_eq!(header.root_directory.0, HEADER_LENGTH);
+        assert_eq!(header.internal_compression, Compression::None);
+        assert_eq!(header.tile_compression, Compression::None);
+        assert_eq!(header.tile_type, TileType::Png);
+        assert_eq!((header.min_zoom, header.max_zoom), (0, 2));
+        assert_eq!(header.bounds, [-180.0, -85.0, 180.0, 85.0]);
+        assert_eq!(header.center_zoom, 1);
+    }
+
+    #[test]
+    fn rejects_bad_magic_and_versions() {
+        let mut bytes = two_level_archive();
+        bytes[7] = 2;
+        assert!(PmTilesHeader::parse(&bytes).is_err());
+        bytes[7] = 3;
+        bytes[0] = b'X';
+        assert!(PmTilesHeader::parse(&bytes).is_err());
+        assert!(PmTilesHeader::parse(b"PMTiles\x03").is_err());
+    }
+
+    #[test]
+    fn rejects_unsupported_compression() {
+        let mut bytes = two_level_archive();
+        bytes[98] = 3;
+        assert!(open(bytes).is_err());
+    }
+
+    #[test]
+    fn reads_tiles_from_root_and_leaf_directories() {
+        let pmtiles = open(two_level_archive()).unwrap();
+        assert_eq!(pmtiles.name(), "test");
+        assert_eq!(pmtiles.tile(0, 0, 0).unwrap().unwrap(), b"abc");
+        for (x, y) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+            assert_eq!(pmtiles.tile(1, x, y).unwrap().unwrap(), b"def");
+        }
+        assert_eq!(pmtiles.tile(2, 0, 0).unwrap(), None);
+        assert!(pmtiles.tile(1, 2, 0).is_err());
+    }
+
+    #[test]
+    fn rejects_leaves_outside_of_their_section() {
+        let leaf = directory(&[(1, 4, 3, 3)]);
+        let root = directory(&[(0, 1, 3, 0), (1, 0, 1000, 0)]);
+        let pmtiles = open(archive(&root, &leaf, b"abcdef")).unwrap();
+        assert!(pmtiles.tile(0, 0, 0).is_ok());
+        assert!(pmtiles.tile(1, 0, 0).is_err());
+
+        let root = directory(&[(1, 0, leaf.len() as u64, u64::MAX - 1)]);
+        let pmtiles = open(archive(&root, &leaf, b"abcdef")).unwrap();
+        assert!(pmtiles.tile(1, 0, 0).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_archives() {
+        let mut bytes = two_level_archive();
+        // the tile data section claims more bytes than the archive has
+        bytes[64..72].copy_from_slice(&1000u64.to_le_bytes());
+        let tiles_start = bytes.len() - 6;
+        bytes.truncate(tiles_start + 4);
+        let pmtiles = open(bytes).unwrap();
+        assert!(pmtiles.tile(0, 0, 0).is_ok());
+        assert!(pmtiles.tile(1, 0, 0).is_err());
+    }
+}
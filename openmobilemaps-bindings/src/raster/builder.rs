@@ -0,0 +1,236 @@
+// Copyright (c) 2023 Ubique Innovation AG <https://www.ubique.ch>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use anyhow::bail;
+use cxx::{SharedPtr, UniquePtr};
+
+use super::{create_raster_layer, default_zoom_info, web_mercator_zoom_levels_with_tile_size};
+use crate::bindings::impls::DefaultLoaderInterface;
+use crate::*;
+
+/// A raster layer together with its loader and the attribution to show with it.
+pub struct RasterLayer {
+    pub loader: SharedPtr<LoaderInterfaceImpl>,
+    pub layer: SharedPtr<LayerInterface>,
+    pub attribution: Option<String>,
+}
+
+/// Builds a web mercator raster layer from a tile URL template.
+///
+/// The template may contain `{z}`, `{x}`, `{y}`, `{-y}` (the row in TMS scheme), `{s}` (one
+/// of the subdomains) and `{t}` (the time index maps-core asks for), e.g.
+/// `https://{s}.tile.openstreetmap.org/{z}/{x}/{y}.png`.
+pub struct RasterLayerBuilder {
+    url_template: String,
+    subdomains: Vec<String>,
+    min_zoom: i32,
+    max_zoom: i32,
+    tile_size: u32,
+    attribution: Option<String>,
+    layer_name: String,
+    loader: Option<Box<dyn LoaderInterfaceTrait>>,
+}
+
+impl RasterLayerBuilder {
+    pub fn new(url_template: impl Into<String>) -> Self {
+        Self {
+            url_template: url_template.into(),
+            subdomains: vec!["a".into(), "b".into(), "c".into()],
+            min_zoom: 0,
+            max_zoom: 19,
+            tile_size: 256,
+            attribution: None,
+            layer_name: "RasterLayer".into(),
+            loader: None,
+        }
+    }
+
+    pub fn subdomains<S: Into<String>>(mut self, subdomains: impl IntoIterator<Item = S>) -> Self {
+        self.subdomains = subdomains.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn min_zoom(mut self, min_zoom: i32) -> Self {
+        self.min_zoom = min_zoom;
+        self
+    }
+
+    pub fn max_zoom(mut self, max_zoom: i32) -> Self {
+        self.max_zoom = max_zoom;
+        self
+    }
+
+    /// Width and height of the tiles in pixels, 256 by default.
+    pub fn tile_size(mut self, tile_size: u32) -> Self {
+        self.tile_size = tile_size;
+        self
+    }
+
+    pub fn attribution(mut self, attribution: impl Into<String>) -> Self {
+        self.attribution = Some(attribution.into());
+        self
+    }
+
+    pub fn layer_name(mut self, layer_name: impl Into<String>) -> Self {
+        self.layer_name = layer_name.into();
+        self
+    }
+
+    /// Loads the tiles through `loader` instead of the default HTTP loader.
+    pub fn loader(mut self, loader: Box<dyn LoaderInterfaceTrait>) -> Self {
+        self.loader = Some(loader);
+        self
+    }
+
+    pub fn build_config(&self) -> anyhow::Result<UrlTemplateConfig> {
+        let template = &self.url_template;
+        if !template.contains("{z}") || !template.contains("{x}") {
+            bail!("URL template {template} needs {{z}} and {{x}}");
+        }
+        if !template.contains("{y}") && !template.contains("{-y}") {
+            bail!("URL template {template} needs {{y}} or {{-y}}");
+        }
+        if template.contains("{s}") && self.subdomains.is_empty() {
+            bail!("URL template {template} uses {{s}} but no subdomains are set");
+        }
+        if !(0..=30).contains(&self.min_zoom) || !(self.min_zoom..=30).contains(&self.max_zoom) {
+            bail!("Invalid zoom range {}..={}", self.min_zoom, self.max_zoom);
+        }
+        if self.tile_size == 0 {
+            bail!("Tile size must not be zero");
+        }
+        Ok(UrlTemplateConfig {
+            url_template: self.url_template.clone(),
+            subdomains: self.subdomains.clone(),
+            min_zoom: self.min_zoom,
+            max_zoom: self.max_zoom,
+            tile_size: self.tile_size,
+            layer_name: self.layer_name.clone(),
+        })
+    }
+
+    pub fn build(self) -> anyhow::Result<RasterLayer> {
+        let config = self.build_config()?;
+        let loader = self
+            .loader
+            .unwrap_or_else(|| Box::new(DefaultLoaderInterface::new(false)));
+        let (loader, layer) = create_raster_layer(Box::new(config), loader)?;
+        Ok(RasterLayer {
+            loader,
+            layer,
+            attribution: self.attribution,
+        })
+    }
+}
+
+/// Layer config of a [`RasterLayerBuilder`].
+pub struct UrlTemplateConfig {
+    url_template: String,
+    subdomains: Vec<String>,
+    min_zoom: i32,
+    max_zoom: i32,
+    tile_size: u32,
+    layer_name: String,
+}
+
+impl UrlTemplateConfig {
+    pub fn tile_url(&self, x: i32, y: i32, t: i32, zoom: i32) -> String {
+        let mut url = self
+            .url_template
+            .replace("{z}", &zoom.to_string())
+            .replace("{x}", &x.to_string())
+            .replace("{y}", &y.to_string())
+            .replace("{-y}", &((1 << zoom) - 1 - y).to_string())
+            .replace("{t}", &t.to_string());
+        if !self.subdomains.is_empty() {
+            // the same tile always comes from the same subdomain so caches stay warm
+            let index = (x as i64 + y as i64).unsigned_abs() as usize % self.subdomains.len();
+            url = url.replace("{s}", &self.subdomains[index]);
+        }
+        url
+    }
+}
+
+impl Tiled2dMapLayerConfigTrait for UrlTemplateConfig {
+    fn getCoordinateSystemIdentifier(&self) -> UniquePtr<cxx::CxxString> {
+        CoordinateSystemIdentifiers::EPSG3857()
+    }
+
+    fn getTileUrl(&self, x: i32, y: i32, t: i32, zoom: i32) -> UniquePtr<cxx::CxxString> {
+        make_string(&self.tile_url(x, y, t, zoom))
+    }
+
+    fn getZoomLevelInfos(&self) -> UniquePtr<CxxVector<Tiled2dMapZoomLevelInfo>> {
+        web_mercator_zoom_levels_with_tile_size(self.min_zoom..=self.max_zoom, self.tile_size)
+    }
+
+    fn getZoomInfo(&self) -> UniquePtr<Tiled2dMapZoomInfo> {
+        default_zoom_info()
+    }
+
+    fn getLayerName(&self) -> UniquePtr<cxx::CxxString> {
+        make_string(&self.layer_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_placeholders() {
+        let config = RasterLayerBuilder::new("https://tiles.example.com/{z}/{x}/{y}/{-y}/{t}.png")
+            .build_config()
+            .unwrap();
+        assert_eq!(
+            config.tile_url(3, 1, 2, 2),
+            "https://tiles.example.com/2/3/1/2/2.png"
+        );
+        assert_eq!(
+            config.tile_url(0, 0, 0, 0),
+            "https://tiles.example.com/0/0/0/0/0.png"
+        );
+    }
+
+    #[test]
+    fn rotates_subdomains() {
+        let config = RasterLayerBuilder::new("https://{s}.example.com/{z}/{x}/{y}.png")
+            .build_config()
+            .unwrap();
+        let subdomain = |x, y| {
+            config
+                .tile_url(x, y, 0, 4)
+                .trim_start_matches("https://")
+                .split('.')
+                .next()
+                .unwrap()
+                .to_owned()
+        };
+        assert_eq!(subdomain(0, 0), "a");
+        assert_eq!(subdomain(1, 0), "b");
+        assert_eq!(subdomain(1, 1), "c");
+        assert_eq!(subdomain(2, 2), "b");
+        // the same tile always comes from the same subdomain
+        assert_eq!(subdomain(5, 7), subdomain(5, 7));
+    }
+
+    #[test]
+    fn allows_templates_without_subdomains() {
+        let config = RasterLayerBuilder::new("https://tiles.example.com/{z}/{x}/{y}.png")
+            .subdomains(Vec::<String>::new())
+            .build_config()
+            .unwrap();
+        assert_eq!(
+            config.tile_url(5, 6, 0, 7),
+            "https://tiles.example.com/7/5/6.png"
+        );
+
+        let missing = RasterLayerBuilder::new("https://{s}.example.com/{z}/{x}/{y}.png")
+            .subdomains(Vec::<String>::new())
+            .build_config();
+        assert!(missing.is_err());
+    }
+}
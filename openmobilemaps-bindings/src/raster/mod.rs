@@ -0,0 +1,190 @@
+// Copyright (c) 2023 Ubique Innovation AG <https://www.ubique.ch>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::{ops::RangeInclusive, sync::Arc};
+
+use anyhow::bail;
+use cxx::{SharedPtr, UniquePtr};
+use image::GenericImageView;
+
+use crate::bindings::impls::{empty_texture_result, texture_result, LoadFailure};
+use crate::cache::{DecodedTile, DecodedTileCache};
+use crate::*;
+
+mod builder;
+mod tile_matrix_set;
+pub use builder::*;
+pub use tile_matrix_set::*;
+
+/// Half the width of the web mercator plane in meters.
+pub const WEB_MERCATOR_EXTENT: f64 = 20037508.34;
+
+/// Zoom behaviour shared by all raster layer configs: unscaled zoom levels, no previous levels
+/// drawn, scale adapted to the screen, no tile masking, under- and overzooming allowed.
+pub fn default_zoom_info() -> UniquePtr<Tiled2dMapZoomInfo> {
+    Tiled2dMapZoomInfo::new(1.0, 0, true, false, true, true).within_unique_ptr()
+}
+
+/// Fails unless maps-core can place tiles in `layer_crs` on a map in `map_crs`. Besides
+/// matching systems that are the ones maps-core ships converters between.
+pub fn check_crs_compatible(layer_crs: &str, map_crs: &str) -> anyhow::Result<()> {
+    if layer_crs == map_crs {
+        return Ok(());
+    }
+    let convertible = [
+        CoordinateSystemIdentifiers::EPSG3857(),
+        CoordinateSystemIdentifiers::EPSG4326(),
+        CoordinateSystemIdentifiers::EPSG2056(),
+        CoordinateSystemIdentifiers::EPSG21781(),
+    ];
+    let is_convertible = |crs: &str| convertible.iter().any(|known| known.to_str() == Ok(crs));
+    if !is_convertible(layer_crs) || !is_convertible(map_crs) {
+        bail!("Layer coordinate system {layer_crs} can not be shown on a {map_crs} map");
+    }
+    Ok(())
+}
+
+/// Builds a raster layer that loads the tiles described by `config` through `loader`.
+pub fn create_raster_layer(
+    config: Box<dyn Tiled2dMapLayerConfigTrait>,
+    loader: Box<dyn LoaderInterfaceTrait>,
+) -> anyhow::Result<(SharedPtr<LoaderInterfaceImpl>, SharedPtr<LayerInterface>)> {
+    let mut builder = Tiled2dMapRasterLayerInterfaceBuilder::builder().within_unique_ptr();
+
+    if builder.is_null() {
+        bail!("Failed to initialize raster layer builder");
+    }
+    let config_wrapper = unsafe {
+        let wrapper = Tiled2dMapLayerConfigWrapperImpl(config);
+        let pointer = Box::into_raw(Box::new(wrapper));
+        Tiled2dMapLayerConfigWrapper::new1(pointer as _).within_unique_ptr()
+    };
+
+    if config_wrapper.is_null() {
+        bail!("Failed to setup config wrapper");
+    }
+
+    let config = Tiled2dMapLayerConfigWrapper::asTiled2dMapLayerConfig(config_wrapper);
+
+    builder.pin_mut().setConfig(config);
+
+    let loader = LoaderInterfaceWrapperImpl(loader);
+    let pointer = Box::into_raw(Box::new(loader));
+    let loader = unsafe { LoaderInterfaceImpl::new1(pointer as _).within_unique_ptr() };
+
+    if loader.is_null() {
+        bail!("Failed to initialize loader");
+    }
+    let loader = LoaderInterfaceImpl::toShared(loader);
+
+    let loader_shared = LoaderInterfaceImpl::asLoaderInterface(loader.clone());
+    builder.pin_mut().addLoader(loader_shared);
+
+    let tiled = builder.pin_mut().build();
+    Ok((loader, down_cast_to_layer_interface(tiled)))
+}
+
+/// Zoom levels of the standard web mercator (EPSG:3857) tile pyramid with 256px tiles.
+pub fn web_mercator_zoom_levels(
+    zoom_levels: RangeInclusive<i32>,
+) -> UniquePtr<CxxVector<Tiled2dMapZoomLevelInfo>> {
+    web_mercator_zoom_levels_with_tile_size(zoom_levels, 256)
+}
+
+/// Zoom levels of the web mercator tile pyramid for tiles of `tile_size` pixels. Larger tiles
+/// are shown at the scale of the 256px level with the same resolution.
+pub fn web_mercator_zoom_levels_with_tile_size(
+    zoom_levels: RangeInclusive<i32>,
+    tile_size: u32,
+) -> UniquePtr<CxxVector<Tiled2dMapZoomLevelInfo>> {
+    TileMatrixSet::web_mercator_quad(zoom_levels, tile_size).zoom_level_infos()
+}
+
+/// Latitude where web mercator is cut off to make the projection square.
+pub const WEB_MERCATOR_MAX_LATITUDE: f64 = 85.05112878;
+
+/// Projects WGS84 longitude and latitude onto web mercator, latitudes beyond
+/// [`WEB_MERCATOR_MAX_LATITUDE`] are clamped to the edge of the plane.
+pub fn lon_lat_to_web_mercator(lon: f64, lat: f64) -> (f64, f64) {
+    let lat = lat.clamp(-WEB_MERCATOR_MAX_LATITUDE, WEB_MERCATOR_MAX_LATITUDE);
+    let x = lon * WEB_MERCATOR_EXTENT / 180.0;
+    let y = ((90.0 + lat) * std::f64::consts::PI / 360.0).tan().ln() / std::f64::consts::PI
+        * WEB_MERCATOR_EXTENT;
+    (x, y)
+}
+
+/// Splits a `<scheme>://<name>/<z>/<x>/<y>` URL as used by the file based sources into its
+/// tile coordinates.
+pub fn parse_tile_url(url: &str, scheme: &str) -> Option<(i32, i32, i32)> {
+    let mut parts = url.strip_prefix(scheme)?.strip_prefix("://")?.rsplit('/');
+    let y = parts.next()?.parse().ok()?;
+    let x = parts.next()?.parse().ok()?;
+    let zoom = parts.next()?.parse().ok()?;
+    Some((zoom, x, y))
+}
+
+/// Decodes `data` into a texture and keeps it in `decoded` for the next load of `url`.
+/// The format is guessed from the data if it is not known.
+pub(crate) fn decoded_texture_result(
+    decoded: &DecodedTileCache,
+    url: &str,
+    data: &[u8],
+    format: Option<image::ImageFormat>,
+) -> UniquePtr<TextureLoaderResult> {
+    let image = match format {
+        Some(format) => image::load_from_memory_with_format(data, format),
+        None => image::load_from_memory(data),
+    };
+    let image = match image {
+        Ok(image) => image,
+        Err(e) => {
+            let failure = LoadFailure::new(
+                url,
+                LoaderStatus::ERROR_OTHER,
+                format!("Failed to decode image: {e}"),
+            );
+            return empty_texture_result(failure.status, &failure.error_code);
+        }
+    };
+    let image_dimensions = image.dimensions();
+    let tile = DecodedTile {
+        width: image_dimensions.0 as usize,
+        height: image_dimensions.1 as usize,
+        rgba: Arc::new(image.into_rgba8().into_raw()),
+        etag: None,
+        expires: None,
+    };
+    decoded.insert(url, tile.clone());
+    texture_result(tile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identifier(identifier: UniquePtr<cxx::CxxString>) -> String {
+        identifier.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn accepts_systems_maps_core_converts_between() {
+        let lv95 = identifier(CoordinateSystemIdentifiers::EPSG2056());
+        let web_mercator = identifier(CoordinateSystemIdentifiers::EPSG3857());
+        assert!(check_crs_compatible(&lv95, &lv95).is_ok());
+        assert!(check_crs_compatible(&lv95, &web_mercator).is_ok());
+        assert!(check_crs_compatible(&web_mercator, &lv95).is_ok());
+        assert!(check_crs_compatible("test:grid", "test:grid").is_ok());
+    }
+
+    #[test]
+    fn rejects_mismatched_systems() {
+        let lv95 = identifier(CoordinateSystemIdentifiers::EPSG2056());
+        let web_mercator = identifier(CoordinateSystemIdentifiers::EPSG3857());
+        assert!(check_crs_compatible("EPSG:9999", &web_mercator).is_err());
+        assert!(check_crs_compatible(&lv95, "test:grid").is_err());
+        assert!(check_crs_compatible("test:grid", "test:other").is_err());
+    }
+}
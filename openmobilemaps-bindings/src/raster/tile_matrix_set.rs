@@ -0,0 +1,325 @@
+// Copyright (c) 2023 Ubique Innovation AG <https://www.ubique.ch>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::ops::RangeInclusive;
+
+use cxx::UniquePtr;
+
+use crate::*;
+
+/// Size of a pixel in meters as defined by OGC, relates scale denominators to resolutions.
+pub const STANDARDIZED_PIXEL_SIZE: f64 = 0.00028;
+
+/// Meters per degree at the equator, the unit OGC uses for scale denominators of sets in
+/// geographic coordinates such as EPSG:4326.
+pub const METERS_PER_DEGREE: f64 = 111319.49079327358;
+
+/// Scale denominator of WebMercatorQuad level 0 for 256px tiles.
+const WEB_MERCATOR_SCALE_0: f64 = 559082264.0287178;
+
+/// Resolutions in meters per pixel of the swisstopo LV95 tile matrix set, levels 0 to 28.
+const LV95_RESOLUTIONS: [f64; 29] = [
+    4000.0, 3750.0, 3500.0, 3250.0, 3000.0, 2750.0, 2500.0, 2250.0, 2000.0, 1750.0, 1500.0, 1250.0,
+    1000.0, 750.0, 650.0, 500.0, 250.0, 100.0, 50.0, 20.0, 10.0, 5.0, 2.5, 2.0, 1.5, 1.0, 0.5,
+    0.25, 0.1,
+];
+
+/// One zoom level of a [`TileMatrixSet`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileMatrix {
+    /// Passed to maps-core as `zoomLevelIdentifier` and used as `{z}` in tile URLs.
+    pub identifier: i32,
+    pub scale_denominator: f64,
+    pub matrix_width: i32,
+    pub matrix_height: i32,
+    /// Column and row of the top left tile of the matrix in the full pyramid, non-zero once
+    /// the set is limited to an area with [`TileMatrixSet::limited_to`].
+    pub first_column: i32,
+    pub first_row: i32,
+}
+
+/// A tile pyramid as described by OGC two dimensional tile matrix sets, with the origin in the
+/// top left corner of `bounds`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileMatrixSet {
+    /// maps-core coordinate system identifier of the tiles.
+    pub crs: String,
+    /// `min_x, min_y, max_x, max_y` in `crs`.
+    pub bounds: [f64; 4],
+    /// Width and height of the tiles in pixels.
+    pub tile_size: u32,
+    pub matrices: Vec<TileMatrix>,
+}
+
+impl TileMatrixSet {
+    /// Builds the matrices from scale denominators, the matrix sizes are the number of tiles
+    /// needed to cover `bounds`.
+    pub fn from_scale_denominators(
+        crs: impl Into<String>,
+        bounds: [f64; 4],
+        tile_size: u32,
+        scale_denominators: impl IntoIterator<Item = (i32, f64)>,
+    ) -> Self {
+        let mut set = Self {
+            crs: crs.into(),
+            bounds,
+            tile_size,
+            matrices: vec![],
+        };
+        let [min_x, min_y, max_x, max_y] = bounds;
+        for (identifier, scale_denominator) in scale_denominators {
+            let tile_width = scale_denominator * STANDARDIZED_PIXEL_SIZE * tile_size as f64
+                / set.meters_per_unit();
+            set.matrices.push(TileMatrix {
+                identifier,
+                scale_denominator,
+                matrix_width: ((max_x - min_x) / tile_width).ceil().max(1.0) as i32,
+                matrix_height: ((max_y - min_y) / tile_width).ceil().max(1.0) as i32,
+                first_column: 0,
+                first_row: 0,
+            });
+        }
+        set
+    }
+
+    /// The OGC WebMercatorQuad set (EPSG:3857) used by OpenStreetMap and most XYZ servers.
+    pub fn web_mercator_quad(zoom_levels: RangeInclusive<i32>, tile_size: u32) -> Self {
+        let extent = super::WEB_MERCATOR_EXTENT;
+        let scale_0 = WEB_MERCATOR_SCALE_0 * 256.0 / tile_size as f64;
+        Self::from_scale_denominators(
+            CoordinateSystemIdentifiers::EPSG3857().to_string_lossy(),
+            [-extent, -extent, extent, extent],
+            tile_size,
+            zoom_levels.map(|zoom| (zoom, scale_0 / (1u64 << zoom) as f64)),
+        )
+    }
+
+    /// The swisstopo tile matrix set for the Swiss LV95 grid (EPSG:2056) with 256px tiles.
+    pub fn swiss_lv95() -> Self {
+        Self::from_scale_denominators(
+            CoordinateSystemIdentifiers::EPSG2056().to_string_lossy(),
+            [2420000.0, 1030000.0, 2900000.0, 1350000.0],
+            256,
+            LV95_RESOLUTIONS
+                .iter()
+                .enumerate()
+                .map(|(zoom, resolution)| (zoom as i32, resolution / STANDARDIZED_PIXEL_SIZE)),
+        )
+    }
+
+    /// Keeps only the matrices with an identifier in `zoom_levels`.
+    pub fn with_zoom_range(mut self, zoom_levels: RangeInclusive<i32>) -> Self {
+        self.matrices
+            .retain(|matrix| zoom_levels.contains(&matrix.identifier));
+        self
+    }
+
+    /// Limits every matrix to the tiles intersecting `area` (`min_x, min_y, max_x, max_y` in
+    /// `crs`), so that maps-core requests no tiles outside of it. Tile URLs have to add the
+    /// [`TileMatrixSet::tile_offset`] to the columns and rows maps-core passes then.
+    pub fn limited_to(mut self, area: [f64; 4]) -> Self {
+        let [min_x, _, _, max_y] = self.bounds;
+        let [area_min_x, area_min_y, area_max_x, area_max_y] = area;
+        for index in 0..self.matrices.len() {
+            let tile_width = self.tile_width(&self.matrices[index]);
+            let matrix = &mut self.matrices[index];
+            let left = min_x + matrix.first_column as f64 * tile_width;
+            let top = max_y - matrix.first_row as f64 * tile_width;
+            let range = |from: f64, to: f64, count: i32| {
+                let first = (from / tile_width).floor().clamp(0.0, (count - 1) as f64) as i32;
+                let last = ((to / tile_width).ceil() as i32 - 1).clamp(first, count - 1);
+                (first, last - first + 1)
+            };
+            let (columns_skipped, columns) =
+                range(area_min_x - left, area_max_x - left, matrix.matrix_width);
+            let (rows_skipped, rows) =
+                range(top - area_max_y, top - area_min_y, matrix.matrix_height);
+            matrix.first_column += columns_skipped;
+            matrix.first_row += rows_skipped;
+            matrix.matrix_width = columns;
+            matrix.matrix_height = rows;
+        }
+        self
+    }
+
+    /// Column and row that have to be added to the tile coordinates maps-core passes for the
+    /// matrix with `identifier`.
+    pub fn tile_offset(&self, identifier: i32) -> (i32, i32) {
+        self.matrices
+            .iter()
+            .find(|matrix| matrix.identifier == identifier)
+            .map_or((0, 0), |matrix| (matrix.first_column, matrix.first_row))
+    }
+
+    /// Meters per unit of `crs`, scale denominators are relative to meters.
+    pub fn meters_per_unit(&self) -> f64 {
+        if self.crs == CoordinateSystemIdentifiers::EPSG4326().to_string_lossy() {
+            METERS_PER_DEGREE
+        } else {
+            1.0
+        }
+    }
+
+    /// Width and height of a tile of `matrix` in units of `crs`.
+    pub fn tile_width(&self, matrix: &TileMatrix) -> f64 {
+        matrix.scale_denominator * STANDARDIZED_PIXEL_SIZE * self.tile_size as f64
+            / self.meters_per_unit()
+    }
+
+    pub fn bounds_rect(&self) -> UniquePtr<RectCoord> {
+        let [min_x, min_y, max_x, max_y] = self.bounds;
+        RectCoord::new(
+            Coord::new(self.crs.as_str(), min_x, max_y, 0.0).within_unique_ptr(),
+            Coord::new(self.crs.as_str(), max_x, min_y, 0.0).within_unique_ptr(),
+        )
+        .within_unique_ptr()
+    }
+
+    /// The zoom level infos maps-core needs in `Tiled2dMapLayerConfig::getZoomLevelInfos`.
+    pub fn zoom_level_infos(&self) -> UniquePtr<CxxVector<Tiled2dMapZoomLevelInfo>> {
+        let mut zoom_infos = make_vec_zoom_level_info();
+        let [min_x, min_y, max_x, max_y] = self.bounds;
+        for matrix in &self.matrices {
+            let tile_width = self.tile_width(matrix);
+            // maps-core counts columns and rows from the top left corner of the bounds
+            let left = min_x + matrix.first_column as f64 * tile_width;
+            let top = max_y - matrix.first_row as f64 * tile_width;
+            let right = max_x.min(left + matrix.matrix_width as f64 * tile_width);
+            let bottom = min_y.max(top - matrix.matrix_height as f64 * tile_width);
+            let bounds = RectCoord::new(
+                Coord::new(self.crs.as_str(), left, top, 0.0).within_unique_ptr(),
+                Coord::new(self.crs.as_str(), right, bottom, 0.0).within_unique_ptr(),
+            )
+            .within_unique_ptr();
+            add_zoom_level_info(
+                zoom_infos.pin_mut(),
+                Tiled2dMapZoomLevelInfo::new(
+                    matrix.scale_denominator,
+                    tile_width as f32,
+                    matrix.matrix_width,
+                    matrix.matrix_height,
+                    1,
+                    matrix.identifier,
+                    &bounds,
+                )
+                .within_unique_ptr()
+                .pin_mut(),
+            );
+        }
+        zoom_infos
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Relative difference allowed to the rounded values of the published tables.
+    const TOLERANCE: f64 = 1e-9;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() <= expected.abs() * TOLERANCE,
+            "{actual} is not {expected}"
+        );
+    }
+
+    fn resolution(set: &TileMatrixSet, matrix: &TileMatrix) -> f64 {
+        set.tile_width(matrix) / set.tile_size as f64
+    }
+
+    #[test]
+    fn web_mercator_quad_matches_ogc_table() {
+        // OGC 17-083r2 Annex D.1: zoom, scale denominator, resolution in meters per pixel
+        let table = [
+            (0, 559082264.0287178, 156543.0339280410),
+            (1, 279541132.0143589, 78271.51696402048),
+            (2, 139770566.0071794, 39135.75848201023),
+            (5, 17471320.75089743, 4891.969810251280),
+            (10, 545978.7734655447, 152.8740565703525),
+            (15, 17061.83667079827, 4.777314267823516),
+            (18, 2132.729584193398, 0.5971642834779395),
+            (24, 33.32389975302188, 0.009330691929342805),
+        ];
+        let set = TileMatrixSet::web_mercator_quad(0..=24, 256);
+        assert_eq!(set.matrices.len(), 25);
+        for (zoom, scale_denominator, expected_resolution) in table {
+            let matrix = &set.matrices[zoom];
+            assert_eq!(matrix.identifier, zoom as i32);
+            assert_close(matrix.scale_denominator, scale_denominator);
+            assert_close(resolution(&set, matrix), expected_resolution);
+            assert_eq!(matrix.matrix_width, 1 << zoom);
+            assert_eq!(matrix.matrix_height, 1 << zoom);
+        }
+        // the origin is the top left corner, the same for every level
+        let [min_x, _, _, max_y] = set.bounds;
+        assert!((min_x + 20037508.3427892).abs() < 0.01, "{min_x}");
+        assert!((max_y - 20037508.3427892).abs() < 0.01, "{max_y}");
+    }
+
+    #[test]
+    fn web_mercator_quad_for_512px_tiles_keeps_tile_widths() {
+        let small = TileMatrixSet::web_mercator_quad(0..=3, 256);
+        let large = TileMatrixSet::web_mercator_quad(0..=3, 512);
+        for (small, large_matrix) in small.matrices.iter().zip(&large.matrices) {
+            assert_close(large.tile_width(large_matrix), small.tile_width(small));
+            assert_close(
+                large_matrix.scale_denominator * 2.0,
+                small.scale_denominator,
+            );
+        }
+    }
+
+    #[test]
+    fn swiss_lv95_matches_swisstopo_table() {
+        // swisstopo WMTS capabilities, TileMatrixSet 2056: zoom, resolution, matrix width and
+        // height
+        let table = [
+            (0, 4000.0, 1, 1),
+            (8, 2000.0, 1, 1),
+            (10, 1500.0, 2, 1),
+            (14, 650.0, 3, 2),
+            (17, 100.0, 19, 13),
+            (19, 20.0, 94, 63),
+            (20, 10.0, 188, 125),
+            (24, 1.5, 1250, 834),
+            (26, 0.5, 3750, 2500),
+            (28, 0.1, 18750, 12500),
+        ];
+        let set = TileMatrixSet::swiss_lv95();
+        assert_eq!(
+            set.crs,
+            CoordinateSystemIdentifiers::EPSG2056().to_string_lossy()
+        );
+        assert_eq!(set.matrices.len(), 29);
+        for (zoom, expected_resolution, width, height) in table {
+            let matrix = &set.matrices[zoom];
+            assert_eq!(matrix.identifier, zoom as i32);
+            assert_close(resolution(&set, matrix), expected_resolution);
+            assert_close(matrix.scale_denominator, expected_resolution / 0.00028);
+            assert_eq!((matrix.matrix_width, matrix.matrix_height), (width, height));
+        }
+        let [min_x, _, _, max_y] = set.bounds;
+        assert_eq!((min_x, max_y), (2420000.0, 1350000.0));
+    }
+
+    #[test]
+    fn limited_to_skips_tiles_outside_of_the_area() {
+        let extent = super::super::WEB_MERCATOR_EXTENT;
+        // the north east quarter of the world
+        let set =
+            TileMatrixSet::web_mercator_quad(0..=2, 256).limited_to([1.0, 1.0, extent, extent]);
+        let sizes: Vec<_> = set
+            .matrices
+            .iter()
+            .map(|matrix| (matrix.matrix_width, matrix.matrix_height))
+            .collect();
+        assert_eq!(sizes, [(1, 1), (1, 1), (2, 2)]);
+        assert_eq!(set.tile_offset(0), (0, 0));
+        assert_eq!(set.tile_offset(1), (1, 0));
+        assert_eq!(set.tile_offset(2), (2, 0));
+    }
+}
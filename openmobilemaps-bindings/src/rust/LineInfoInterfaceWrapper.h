@@ -11,6 +11,9 @@
 #include "SizeType.h"
 #include "LineCapType.h"
 #include "Coord.h"
+#include "Color.h"
+#include "LayerInterface.h"
+#include "LineLayerInterface.h"
 #include <string>
 #include <memory>
 
@@ -55,7 +58,37 @@ class LineStyleBuilder {
 
 };
 
+// an empty dash array draws a solid line
 inline std::vector<float> make_default_dash()
 {
-     return std::vector<float>(1.0);
+     return std::vector<float>();
+}
+
+inline std::vector<float> make_vec_float() { return std::vector<float>(); }
+
+inline void add_float(std::vector<float> &values, float value)
+{
+     values.push_back(value);
+}
+
+inline std::vector<Coord> make_vec_coord() { return std::vector<Coord>(); }
+
+inline void add_coord(std::vector<Coord> &coords, const Coord &coord)
+{
+     coords.push_back(coord);
+}
+
+inline std::vector<Coord> copy_coords(const std::vector<Coord> &coords) { return coords; }
+
+inline std::unique_ptr<LineStyle> copy_line_style(const LineStyle &style) { return std::make_unique<LineStyle>(style); }
+
+// the same colors are used for the normal and the highlighted state
+inline std::unique_ptr<LineStyle> make_line_style(const Color &color, const Color &gapColor, float opacity, SizeType widthType, float width, const std::vector<float> &dashArray, LineCapType lineCap)
+{
+     return std::make_unique<LineStyle>(ColorStateList(color, color), ColorStateList(gapColor, gapColor), opacity, widthType, width, dashArray, lineCap);
+}
+
+inline std::shared_ptr<LayerInterface> line_layer_as_layer_interface(std::shared_ptr<LineLayerInterface> layer)
+{
+     return layer->asLayerInterface();
 }
\ No newline at end of file
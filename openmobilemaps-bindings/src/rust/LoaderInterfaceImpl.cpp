@@ -42,10 +42,12 @@ DataLoaderResult LoaderInterfaceImpl::loadData(const std::string &url, const std
 
     if (etag.has_value())
     {
-        return *this->rustBox->loadDataWrapper(url, std::make_unique<std::string>(*etag)).release();
+        auto result = this->rustBox->loadDataWrapper(url, std::make_unique<std::string>(*etag));
+        return *result;
     }
     else
     {
-        return *this->rustBox->loadDataWrapper(url, std::make_unique<std::string>(std::string(""))).release();
+        auto result = this->rustBox->loadDataWrapper(url, std::make_unique<std::string>(std::string("")));
+        return *result;
     }
 }
\ No newline at end of file
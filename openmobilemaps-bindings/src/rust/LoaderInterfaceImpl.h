@@ -13,6 +13,8 @@
 
 struct LoaderInterfaceWrapperImpl;
 
+std::unique_ptr<DataLoaderResult> make_data_loader_result(::rust::Slice<const uint8_t> data, LoaderStatus status, const std::string &etag, const std::string &errorCode);
+
 class LoaderInterfaceImpl : public LoaderInterface
 {
   ::rust::Box<LoaderInterfaceWrapperImpl> rustBox;
@@ -72,13 +72,23 @@ void ColorLineGroup2dShaderOpenGl::setStyles(const std::vector<::LineStyle> &lin
         gapColorValues[sizeGapColorValues * i + 1] = style.gapColor.normal.g;
         gapColorValues[sizeGapColorValues * i + 2] = style.gapColor.normal.b;
         gapColorValues[sizeGapColorValues * i + 3] = style.gapColor.normal.a * style.opacity;
-        int numDashInfo = std::min((int)style.dashArray.size(), maxNumDashValues); // Max num dash infos: 4 (2 dash/gap lengths)
-        dashValues[sizeDashValues * i] = numDashInfo;
+        // like in SVG an odd number of dash values is repeated to get pairs of dash and gap lengths
+        std::vector<float> dashArray = style.dashArray;
+        if (dashArray.size() % 2 == 1) {
+            dashArray.insert(dashArray.end(), style.dashArray.begin(), style.dashArray.end());
+        }
+        int numDashInfo = std::min((int)dashArray.size(), maxNumDashValues) / 2 * 2; // Max num dash infos: 4 (2 dash/gap lengths)
         float sum = 0.0;
         for (int iDash = 0; iDash < numDashInfo; iDash++) {
-            sum += style.dashArray.at(iDash);
+            if (dashArray.at(iDash) < 0.0) {
+                sum = 0.0;
+                break;
+            }
+            sum += dashArray.at(iDash);
             dashValues[sizeDashValues * i + 1 + iDash] = sum;
         }
+        // negative or all zero values draw a solid line
+        dashValues[sizeDashValues * i] = sum > 0.0 ? numDashInfo : 0;
     }
 
     {
@@ -189,8 +199,9 @@ std::string ColorLineGroup2dShaderOpenGl::getFragmentShader() {
                                     vec4 fragColor = color;
                                     int dashBase = 5 * int(fLineIndex);
                                     int numDashInfos = int(floor(lineDashValues[dashBase] + 0.5));
-                                    if (numDashInfos > 0) {
+                                    if (numDashInfos > 0 && lineLength > 0.0) {
                                         int baseDashInfos = dashBase + 1;
+                                        // dash lengths are given in multiples of the line width
                                         float factorToT = radius * 2.0 / lineLength;
                                         float dashTotal = lineDashValues[baseDashInfos + (numDashInfos - 1)] * factorToT;
                                         float startOffsetSegment = mod(segmentStartLPos / lineLength, dashTotal);
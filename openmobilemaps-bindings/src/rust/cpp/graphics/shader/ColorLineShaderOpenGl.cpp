@@ -71,13 +71,23 @@ void ColorLineShaderOpenGl::setStyle(const LineStyle &lineStyle) {
     gapColorValues[1] = (isHighlighted) ? lineStyle.gapColor.highlighted.g : lineStyle.gapColor.normal.g;
     gapColorValues[2] = (isHighlighted) ? lineStyle.gapColor.highlighted.b : lineStyle.gapColor.normal.b;
     gapColorValues[3] = ((isHighlighted) ? lineStyle.gapColor.highlighted.a : lineStyle.gapColor.normal.a) * lineStyle.opacity;
-    int numDashInfo = std::min((int)lineStyle.dashArray.size(), maxNumDashValues); // Max num dash infos: 4 (2 dash/gap lengths)
-    dashValues[0] = numDashInfo;
+    // like in SVG an odd number of dash values is repeated to get pairs of dash and gap lengths
+    std::vector<float> dashArray = lineStyle.dashArray;
+    if (dashArray.size() % 2 == 1) {
+        dashArray.insert(dashArray.end(), lineStyle.dashArray.begin(), lineStyle.dashArray.end());
+    }
+    int numDashInfo = std::min((int)dashArray.size(), maxNumDashValues) / 2 * 2; // Max num dash infos: 4 (2 dash/gap lengths)
     float sum = 0.0;
     for (int iDash = 0; iDash < numDashInfo; iDash++) {
-        sum += lineStyle.dashArray.at(iDash);
+        if (dashArray.at(iDash) < 0.0) {
+            sum = 0.0;
+            break;
+        }
+        sum += dashArray.at(iDash);
         dashValues[1 + iDash] = sum;
     }
+    // negative or all zero values draw a solid line
+    dashValues[0] = sum > 0.0 ? numDashInfo : 0;
 
     {
         std::lock_guard<std::recursive_mutex> overlayLock(styleMutex);
@@ -196,9 +206,10 @@ std::string ColorLineShaderOpenGl::getFragmentShader() {
 
                                     vec4 fragColor = color;
                                     int numDashInfos = int(floor(lineDashValues[0] + 0.5));
-                                    if (numDashInfos > 0)
+                                    if (numDashInfos > 0 && lineLength > 0.0)
                                     {
                                         int baseDashInfos = 1;
+                                        // dash lengths are given in multiples of the line width
                                         float factorToT = radius * 2.0 / lineLength;
                                         float dashTotal = lineDashValues[baseDashInfos + (numDashInfos - 1)] * factorToT;
                                         float startOffsetSegment = mod(segmentStartLPos / lineLength, dashTotal);
@@ -5,6 +5,7 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 #include "make_shared.h"
+#include "LoaderInterfaceImpl.h"
 
 std::shared_ptr<SchedulerInterface> transform_unique(std::unique_ptr<SchedulerInterfaceStaticWrapper> ptr)
 {
@@ -17,12 +18,61 @@ std::shared_ptr<TextureHolderInterface> transform_texture_holder_interface(std::
     return ptr;
 };
 
-std::unique_ptr<TextureLoaderResult> make_loader_result(std::shared_ptr<TextureHolderInterface> ptr, LoaderStatus status)
+// empty strings stand for missing values
+std::unique_ptr<TextureLoaderResult> make_loader_result(std::shared_ptr<TextureHolderInterface> ptr, LoaderStatus status, const std::string &etag, const std::string &errorCode)
 {
-    return std::make_unique<TextureLoaderResult>(ptr, std::nullopt, status, std::nullopt);
+    std::optional<std::string> etagValue = std::nullopt;
+    if (!etag.empty())
+    {
+        etagValue = etag;
+    }
+    std::optional<std::string> errorCodeValue = std::nullopt;
+    if (!errorCode.empty())
+    {
+        errorCodeValue = errorCode;
+    }
+    return std::make_unique<TextureLoaderResult>(ptr, etagValue, status, errorCodeValue);
+}
+
+// empty strings stand for missing values, data is only passed on for successful loads
+std::unique_ptr<DataLoaderResult> make_data_loader_result(::rust::Slice<const uint8_t> data, LoaderStatus status, const std::string &etag, const std::string &errorCode)
+{
+    std::optional<::djinni::DataRef> dataRef = std::nullopt;
+    if (status == LoaderStatus::OK)
+    {
+        // copies the bytes out of the Rust slice in one go
+        dataRef = ::djinni::DataRef(data.data(), data.size());
+    }
+    std::optional<std::string> theEtag = etag.empty() ? std::nullopt : std::optional<std::string>(etag);
+    std::optional<std::string> theErrorCode = errorCode.empty() ? std::nullopt : std::optional<std::string>(errorCode);
+    return std::make_unique<DataLoaderResult>(dataRef, theEtag, status, theErrorCode);
 }
 
 std::shared_ptr<LayerInterface> down_cast_to_layer_interface(std::shared_ptr<Tiled2dMapRasterLayerInterface> ptr)
 {
     return ptr->asLayerInterface();
-}
\ No newline at end of file
+}
+// a null pointer signals that maps-core has no converter between the systems
+std::unique_ptr<Coord> convert_coord(const std::string &to, const Coord &coord)
+{
+    try
+    {
+        return std::make_unique<Coord>(CoordinateConversionHelperInterface::independentInstance()->convert(to, coord));
+    }
+    catch (const std::exception &e)
+    {
+        return nullptr;
+    }
+}
+
+std::unique_ptr<RectCoord> convert_rect(const std::string &to, const RectCoord &rect)
+{
+    try
+    {
+        return std::make_unique<RectCoord>(CoordinateConversionHelperInterface::independentInstance()->convertRect(to, rect));
+    }
+    catch (const std::exception &e)
+    {
+        return nullptr;
+    }
+}
@@ -7,26 +7,29 @@
 
 #include "SchedulerInterface.h"
 #include "Coord.h"
+#include "CoordinateConversionHelperInterface.h"
 #include "CoordinateSystemIdentifiers.h"
 #include "LayerInterface.h"
 #include "MapCallbackInterface.h"
 #include "MapReadyCallbackInterface.h"
 #include "PolygonCoord.h"
+#include "RectCoord.h"
 #include "SchedulerInterfaceStaticWrapper.h"
 #include "TaskConfig.h"
 #include "TaskInterface.h"
+#include "TaskPriority.h"
 #include "TextureHolderInterface.h"
 #include "TextureLoaderResult.h"
+#include "DataLoaderResult.h"
 #include "Tiled2dMapRasterLayerInterface.h"
 #include "Tiled2dMapZoomLevelInfo.h"
 #include "IconInfoInterface.h"
+#include "LambdaTask.h"
 #include <iostream>
 #include <memory>
+#include <mutex>
 #include <string>
 
-#include <chrono>
-#include <thread>
-
 
 template <typename T>
 std::shared_ptr<T> transform_unique_internal(std::unique_ptr<T> &ptr)
@@ -38,7 +41,12 @@ std::shared_ptr<SchedulerInterface> transform_unique(std::unique_ptr<SchedulerIn
 std::shared_ptr<TextureHolderInterface> transform_texture_holder_interface(std::unique_ptr<TextureHolderInterface> ptr);
 std::shared_ptr<MapReadyCallbackInterface> transform_ready_state(std::unique_ptr<MapReadyCallbackInterface> ptr) { return ptr; }
 
-std::unique_ptr<TextureLoaderResult> make_loader_result(std::shared_ptr<TextureHolderInterface>, LoaderStatus status);
+std::unique_ptr<TextureLoaderResult> make_loader_result(std::shared_ptr<TextureHolderInterface>, LoaderStatus status, const std::string &etag, const std::string &errorCode);
+inline LoaderStatus loader_result_status(const TextureLoaderResult &result) { return result.status; }
+inline std::string loader_result_error_code(const TextureLoaderResult &result) { return result.errorCode.value_or(""); }
+inline int32_t loader_result_texture_width(const TextureLoaderResult &result) { return result.data ? result.data->getImageWidth() : 0; }
+inline LoaderStatus data_result_status(const DataLoaderResult &result) { return result.status; }
+inline size_t data_result_len(const DataLoaderResult &result) { return result.data ? result.data->len() : 0; }
 std::shared_ptr<IconInfoInterface> transform_icon_info_interface(std::unique_ptr<IconInfoInterface> ptr) { return ptr;  }
 std::shared_ptr<LayerInterface> down_cast_to_layer_interface(std::shared_ptr<Tiled2dMapRasterLayerInterface> ptr);
 
@@ -54,10 +62,12 @@ inline void add_zoom_level_info(std::vector<Tiled2dMapZoomLevelInfo> &zoomLevels
     zoomLevels.push_back(zoomLevel);
 }
 inline std::string get_id(std::shared_ptr<TaskInterface> interface) { return interface->getConfig().id; }
+inline int64_t get_delay(std::shared_ptr<TaskInterface> interface) { return interface->getConfig().delay; }
+inline TaskPriority get_priority(std::shared_ptr<TaskInterface> interface) { return interface->getConfig().priority; }
+
+// the scheduler waits for the configured delay before handing out the task
 inline void run_task(std::shared_ptr<TaskInterface> interface)
 {
-    auto config = interface->getConfig();
-    std::this_thread::sleep_for(std::chrono::milliseconds(config.delay));
     interface->run();
 }
 
@@ -66,6 +76,43 @@ inline bool is_graphics(std::shared_ptr<TaskInterface> interface)
     return interface->getConfig().executionEnvironment == ExecutionEnvironment::GRAPHICS;
 }
 
+// records the ids of the tasks made by make_logging_task in the order they ran
+class TaskLog
+{
+    mutable std::mutex mutex;
+    std::string ids;
+
+public:
+    void append(const std::string &id)
+    {
+        std::lock_guard<std::mutex> lock(mutex);
+        ids += ids.empty() ? id : "," + id;
+    }
+    std::string text() const
+    {
+        std::lock_guard<std::mutex> lock(mutex);
+        return ids;
+    }
+};
+
+inline std::shared_ptr<TaskLog> make_task_log() { return std::make_shared<TaskLog>(); }
+
+inline std::shared_ptr<TaskInterface> make_logging_task(const std::string &id, int64_t delay, TaskPriority priority, bool graphics, std::shared_ptr<TaskLog> log)
+{
+    auto environment = graphics ? ExecutionEnvironment::GRAPHICS : ExecutionEnvironment::IO;
+    return std::make_shared<LambdaTask>(TaskConfig(id, delay, priority, environment), [id, log] { log->append(id); });
+}
+
+inline std::string coord_system_identifier(const Coord &coord) { return coord.systemIdentifier; }
+inline double coord_x(const Coord &coord) { return coord.x; }
+inline double coord_y(const Coord &coord) { return coord.y; }
+inline double coord_z(const Coord &coord) { return coord.z; }
+inline std::unique_ptr<Coord> rect_top_left(const RectCoord &rect) { return std::make_unique<Coord>(rect.topLeft); }
+inline std::unique_ptr<Coord> rect_bottom_right(const RectCoord &rect) { return std::make_unique<Coord>(rect.bottomRight); }
+
+std::unique_ptr<Coord> convert_coord(const std::string &to, const Coord &coord);
+std::unique_ptr<RectCoord> convert_rect(const std::string &to, const RectCoord &rect);
+
 // inline std::vector<> get_loader_list(Tiled2dMapRasterLayerInterface li) { li-> }
 
 inline std::unique_ptr<PolygonCoord> make_polygon_coord()
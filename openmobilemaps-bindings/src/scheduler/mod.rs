@@ -0,0 +1,580 @@
+// Copyright (c) 2023 Ubique Innovation AG <https://www.ubique.ch>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context};
+
+use crate::{get_delay, get_id, get_priority, is_graphics, run_task, TaskInterface, TaskPriority};
+
+pub type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Runs the jobs of a scheduler. maps-core calls the scheduler from several threads, so
+/// spawners have to be shareable between them.
+pub trait TaskSpawner: Send + Sync {
+    fn spawn_blocking(&self, job: Job);
+    /// Runs `job` like [`TaskSpawner::spawn_blocking`] once `delay` has passed, without
+    /// occupying a thread while waiting.
+    fn spawn_blocking_delayed(&self, delay: Duration, job: Job);
+}
+
+/// Runs tasks on a tokio runtime, either one of its own or one the application already has.
+pub struct DefaultSpawner {
+    handle: tokio::runtime::Handle,
+    runtime: Option<tokio::runtime::Runtime>,
+}
+
+impl DefaultSpawner {
+    /// The runtime of `handle` needs the time driver, delayed tasks panic without it.
+    pub fn from_handle(handle: tokio::runtime::Handle) -> Self {
+        Self {
+            handle,
+            runtime: None,
+        }
+    }
+
+    pub fn from_runtime(runtime: tokio::runtime::Runtime) -> Self {
+        Self {
+            handle: runtime.handle().clone(),
+            runtime: Some(runtime),
+        }
+    }
+}
+
+impl Drop for DefaultSpawner {
+    fn drop(&mut self) {
+        // the map may be dropped from within an async context, where blocking is not allowed
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+impl TaskSpawner for DefaultSpawner {
+    fn spawn_blocking(&self, job: Job) {
+        self.handle.spawn_blocking(job);
+    }
+
+    fn spawn_blocking_delayed(&self, delay: Duration, job: Job) {
+        self.handle.spawn(async move {
+            tokio::time::sleep(delay).await;
+            tokio::task::spawn_blocking(job);
+        });
+    }
+}
+
+/// Queue of jobs that run on the render thread whenever it drains its [`TaskReceiver`].
+#[derive(Clone, Default)]
+struct InlineQueue(Arc<Mutex<VecDeque<(Instant, Job)>>>);
+
+impl InlineQueue {
+    fn push(&self, due: Instant, job: Job) {
+        self.0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push_back((due, job));
+    }
+
+    /// The job spawned first among the ones that are due.
+    fn pop(&self) -> Option<Job> {
+        let mut queue = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let index = queue.iter().position(|(due, _)| *due <= now)?;
+        queue.remove(index).map(|(_, job)| job)
+    }
+}
+
+/// Defers every job to the render thread and runs them one after the other in the order they
+/// were spawned. Delayed jobs join in once their delay has passed.
+struct InlineSpawner {
+    queue: InlineQueue,
+}
+
+impl TaskSpawner for InlineSpawner {
+    fn spawn_blocking(&self, job: Job) {
+        self.queue.push(Instant::now(), job);
+    }
+
+    fn spawn_blocking_delayed(&self, delay: Duration, job: Job) {
+        self.queue.push(Instant::now() + delay, job);
+    }
+}
+
+enum SpawnerChoice {
+    OwnedRuntime,
+    Handle(tokio::runtime::Handle),
+    Custom(Box<dyn TaskSpawner + Send + Sync>),
+    Inline,
+}
+
+/// Configures where the tasks of a map run.
+///
+/// By default a private runtime with one worker thread and up to five blocking threads is
+/// created. Pass a [`tokio::runtime::Handle`] to run inside an existing runtime instead, or a
+/// custom [`TaskSpawner`] to take over completely.
+pub struct SchedulerBuilder {
+    spawner: SpawnerChoice,
+    worker_threads: usize,
+    max_blocking_threads: usize,
+    thread_keep_alive: Duration,
+}
+
+impl Default for SchedulerBuilder {
+    fn default() -> Self {
+        Self {
+            spawner: SpawnerChoice::OwnedRuntime,
+            worker_threads: 1,
+            max_blocking_threads: 5,
+            thread_keep_alive: Duration::from_secs(5),
+        }
+    }
+}
+
+impl SchedulerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the tasks on an existing runtime, the thread limits are ignored then. The runtime
+    /// needs the time driver for delayed tasks, building fails without it.
+    pub fn handle(mut self, handle: tokio::runtime::Handle) -> Self {
+        self.spawner = SpawnerChoice::Handle(handle);
+        self
+    }
+
+    pub fn spawner(mut self, spawner: impl TaskSpawner + 'static) -> Self {
+        self.spawner = SpawnerChoice::Custom(Box::new(spawner));
+        self
+    }
+
+    /// Runs all tasks, graphics or not, on the render thread by priority and then in the order
+    /// they were added. Delayed tasks queue up once their delay has passed.
+    ///
+    /// Tiles then always load in the same order, so the same inputs produce identical frames.
+    /// The tasks run when the render thread calls [`TaskReceiver::run_pending`].
+    pub fn deterministic(mut self) -> Self {
+        self.spawner = SpawnerChoice::Inline;
+        self
+    }
+
+    pub fn worker_threads(mut self, worker_threads: usize) -> Self {
+        self.worker_threads = worker_threads;
+        self
+    }
+
+    pub fn max_blocking_threads(mut self, max_blocking_threads: usize) -> Self {
+        self.max_blocking_threads = max_blocking_threads;
+        self
+    }
+
+    pub fn thread_keep_alive(mut self, thread_keep_alive: Duration) -> Self {
+        self.thread_keep_alive = thread_keep_alive;
+        self
+    }
+
+    pub fn build(self) -> anyhow::Result<(SchedulerInterfaceRust, TaskReceiver)> {
+        if self.worker_threads == 0 || self.max_blocking_threads == 0 {
+            bail!("The scheduler needs at least one worker and one blocking thread");
+        }
+        let mut inline = None;
+        let spawner: Box<dyn TaskSpawner + Send + Sync> = match self.spawner {
+            SpawnerChoice::OwnedRuntime => {
+                let runtime = tokio::runtime::Builder::new_multi_thread()
+                    .enable_all()
+                    .max_blocking_threads(self.max_blocking_threads)
+                    .worker_threads(self.worker_threads)
+                    .thread_keep_alive(self.thread_keep_alive)
+                    .build()
+                    .context("Failed to build internal tasks runtime")?;
+                Box::new(DefaultSpawner::from_runtime(runtime))
+            }
+            SpawnerChoice::Handle(handle) => {
+                if !has_time_driver(&handle) {
+                    bail!("Delayed tasks need a runtime built with the time driver enabled");
+                }
+                Box::new(DefaultSpawner::from_handle(handle))
+            }
+            SpawnerChoice::Custom(spawner) => spawner,
+            SpawnerChoice::Inline => {
+                let queue = InlineQueue::default();
+                inline = Some(queue.clone());
+                Box::new(InlineSpawner { queue })
+            }
+        };
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let state = SharedState::default();
+        state.lock().inline = inline.is_some();
+        Ok((
+            SchedulerInterfaceRust {
+                spawner,
+                channel: sender,
+                state: state.clone(),
+            },
+            TaskReceiver {
+                receiver,
+                state,
+                inline,
+            },
+        ))
+    }
+}
+
+/// tokio can't be asked for the drivers of a runtime, but creating a timer panics without one.
+fn has_time_driver(handle: &tokio::runtime::Handle) -> bool {
+    let _guard = handle.enter();
+    std::panic::catch_unwind(|| drop(tokio::time::sleep(Duration::ZERO))).is_ok()
+}
+
+type QueuedTask = (u64, cxx::SharedPtr<TaskInterface>);
+
+/// A task waiting for a blocking thread, ordered by priority and then by insertion.
+struct ReadyTask {
+    priority: u8,
+    ticket: u64,
+    task: cxx::SharedPtr<TaskInterface>,
+}
+
+impl ReadyTask {
+    fn new(ticket: u64, task: cxx::SharedPtr<TaskInterface>) -> Self {
+        let priority = match get_priority(task.clone()) {
+            TaskPriority::HIGH => 2,
+            TaskPriority::NORMAL => 1,
+            _ => 0,
+        };
+        Self {
+            priority,
+            ticket,
+            task,
+        }
+    }
+
+    fn key(&self) -> (u8, Reverse<u64>) {
+        (self.priority, Reverse(self.ticket))
+    }
+}
+
+impl PartialEq for ReadyTask {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for ReadyTask {}
+
+impl PartialOrd for ReadyTask {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ReadyTask {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+/// Book keeping shared between the scheduler and the jobs it handed out.
+///
+/// Every task gets a ticket when it is added. A task only runs if its ticket is still pending
+/// when its turn comes, so removing or clearing tasks just drops their tickets.
+///
+/// Each spawned job runs whichever ready task has the highest priority at that moment rather
+/// than a fixed one, so high priority tasks overtake queued low priority work.
+#[derive(Default)]
+struct SchedulerState {
+    next_ticket: u64,
+    pending: HashMap<u64, String>,
+    paused: bool,
+    inline: bool,
+    held: VecDeque<QueuedTask>,
+    ready: BinaryHeap<ReadyTask>,
+}
+
+#[derive(Clone, Default)]
+struct SharedState(Arc<Mutex<SchedulerState>>);
+
+impl SharedState {
+    fn lock(&self) -> MutexGuard<'_, SchedulerState> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn register(&self, id: String) -> u64 {
+        let mut state = self.lock();
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        state.pending.insert(ticket, id);
+        ticket
+    }
+
+    /// Marks the task as started, returns false if it was removed in the meantime.
+    fn take(&self, ticket: u64) -> bool {
+        self.lock().pending.remove(&ticket).is_some()
+    }
+
+    fn push_ready(&self, ticket: u64, task: cxx::SharedPtr<TaskInterface>) {
+        self.lock().ready.push(ReadyTask::new(ticket, task));
+    }
+
+    /// Runs the ready task with the highest priority that wasn't removed.
+    fn run_next(&self) {
+        let task = {
+            let mut state = self.lock();
+            loop {
+                let Some(ready) = state.ready.pop() else {
+                    return;
+                };
+                if state.pending.remove(&ready.ticket).is_some() {
+                    break ready.task;
+                }
+                log::debug!("skipping removed task: {}", get_id(ready.task));
+            }
+        };
+        log::debug!("running: {}", get_id(task.clone()));
+        run_task(task.clone());
+        log::debug!("finished: {}", get_id(task));
+    }
+}
+
+pub struct SchedulerInterfaceRust {
+    pub spawner: Box<dyn TaskSpawner + Send + Sync>,
+    channel: Sender<QueuedTask>,
+    state: SharedState,
+}
+
+/// Receives the tasks that have to run on the graphics thread.
+pub struct TaskReceiver {
+    receiver: Receiver<QueuedTask>,
+    state: SharedState,
+    inline: Option<InlineQueue>,
+}
+
+impl TaskReceiver {
+    /// Returns the next graphics task that was neither removed nor cleared.
+    pub fn try_recv(&self) -> Result<cxx::SharedPtr<TaskInterface>, TryRecvError> {
+        loop {
+            let (ticket, task) = self.receiver.try_recv()?;
+            if self.state.take(ticket) {
+                return Ok(task);
+            }
+            log::debug!("skipping removed task: {}", get_id(task));
+        }
+    }
+
+    /// Runs all graphics tasks and, for deterministic schedulers, all other queued tasks.
+    /// Returns the number of tasks that ran.
+    pub fn run_pending(&self) -> usize {
+        let mut ran = 0;
+        while let Ok(task) = self.try_recv() {
+            run_task(task);
+            ran += 1;
+        }
+        if let Some(inline) = &self.inline {
+            while let Some(job) = inline.pop() {
+                job();
+                ran += 1;
+            }
+        }
+        ran
+    }
+}
+
+impl SchedulerInterfaceRust {
+    pub fn new() -> anyhow::Result<(Self, TaskReceiver)> {
+        SchedulerBuilder::new().build()
+    }
+
+    pub fn builder() -> SchedulerBuilder {
+        SchedulerBuilder::new()
+    }
+
+    fn spawn(&self, ticket: u64, task: cxx::SharedPtr<TaskInterface>) {
+        let delay = get_delay(task.clone());
+        let state = self.state.clone();
+        if delay > 0 {
+            self.spawner.spawn_blocking_delayed(
+                Duration::from_millis(delay as u64),
+                Box::new(move || {
+                    state.push_ready(ticket, task);
+                    state.run_next();
+                }),
+            );
+        } else {
+            state.push_ready(ticket, task);
+            self.spawner
+                .spawn_blocking(Box::new(move || state.run_next()));
+        }
+    }
+
+    fn send_graphics(&self, ticket: u64, task: cxx::SharedPtr<TaskInterface>) {
+        let delay = get_delay(task.clone());
+        let channel = self.channel.clone();
+        let send = move || {
+            if channel.send((ticket, task)).is_err() {
+                log::error!("Could not submit task");
+            }
+        };
+        if delay > 0 {
+            self.spawner
+                .spawn_blocking_delayed(Duration::from_millis(delay as u64), Box::new(send));
+        } else {
+            send();
+        }
+    }
+
+    pub(crate) fn addTaskRust(&self, task: cxx::SharedPtr<TaskInterface>) {
+        let ticket = self.state.register(get_id(task.clone()));
+        let inline = self.state.lock().inline;
+        let graphics = is_graphics(task.clone());
+        // inline schedulers already run everything on the graphics thread
+        if graphics && !inline {
+            self.send_graphics(ticket, task);
+            return;
+        }
+        {
+            // pausing only holds back IO and computation tasks
+            let mut state = self.state.lock();
+            if state.paused && !graphics {
+                state.held.push_back((ticket, task));
+                return;
+            }
+        }
+        self.spawn(ticket, task);
+    }
+
+    pub(crate) fn removeTaskRust(&self, id: String) {
+        log::debug!("removeTask {id}");
+        let mut state = self.state.lock();
+        state.pending.retain(|_, task_id| *task_id != id);
+        let SchedulerState { pending, held, .. } = &mut *state;
+        held.retain(|(ticket, _)| pending.contains_key(ticket));
+    }
+
+    pub(crate) fn clearRust(&self) {
+        log::debug!("clear");
+        let mut state = self.state.lock();
+        state.pending.clear();
+        state.held.clear();
+        state.ready.clear();
+    }
+
+    pub(crate) fn pauseRust(&self) {
+        log::debug!("pause");
+        self.state.lock().paused = true;
+    }
+
+    pub(crate) fn resumeRust(&self) {
+        log::debug!("resume");
+        let held = {
+            let mut state = self.state.lock();
+            state.paused = false;
+            std::mem::take(&mut state.held)
+        };
+        for (ticket, task) in held {
+            self.spawn(ticket, task);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cxx::let_cxx_string;
+
+    use super::*;
+    use crate::{make_logging_task, make_task_log, TaskLog};
+
+    /// A deterministic scheduler with tasks that log their ids when they run.
+    struct Fixture {
+        scheduler: SchedulerInterfaceRust,
+        receiver: TaskReceiver,
+        log: cxx::SharedPtr<TaskLog>,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let (scheduler, receiver) = SchedulerBuilder::new().deterministic().build().unwrap();
+            Self {
+                scheduler,
+                receiver,
+                log: make_task_log(),
+            }
+        }
+
+        fn add(&self, id: &str, delay_ms: i64, priority: TaskPriority, graphics: bool) {
+            let_cxx_string!(id = id);
+            let task = make_logging_task(&id, delay_ms, priority, graphics, self.log.clone());
+            self.scheduler.addTaskRust(task);
+        }
+
+        /// Ids of the tasks that ran so far, separated by commas.
+        fn ran(&self) -> String {
+            self.log.text().to_string_lossy().into_owned()
+        }
+    }
+
+    #[test]
+    fn runs_higher_priorities_first() {
+        let fixture = Fixture::new();
+        fixture.add("low", 0, TaskPriority::LOW, false);
+        fixture.add("normal", 0, TaskPriority::NORMAL, false);
+        fixture.add("high", 0, TaskPriority::HIGH, false);
+        fixture.add("graphics", 0, TaskPriority::HIGH, true);
+
+        assert_eq!(fixture.receiver.run_pending(), 4);
+        assert_eq!(fixture.ran(), "high,graphics,normal,low");
+    }
+
+    #[test]
+    fn never_runs_removed_tasks() {
+        let fixture = Fixture::new();
+        fixture.add("removed", 0, TaskPriority::NORMAL, false);
+        fixture.add("kept", 0, TaskPriority::NORMAL, false);
+        fixture.scheduler.removeTaskRust("removed".into());
+
+        fixture.receiver.run_pending();
+        assert_eq!(fixture.ran(), "kept");
+
+        fixture.add("cleared", 0, TaskPriority::NORMAL, true);
+        fixture.scheduler.clearRust();
+        fixture.receiver.run_pending();
+        assert_eq!(fixture.ran(), "kept");
+    }
+
+    #[test]
+    fn holds_io_tasks_while_paused() {
+        let fixture = Fixture::new();
+        fixture.scheduler.pauseRust();
+        fixture.add("io", 0, TaskPriority::HIGH, false);
+        fixture.add("graphics", 0, TaskPriority::NORMAL, true);
+
+        fixture.receiver.run_pending();
+        assert_eq!(fixture.ran(), "graphics");
+
+        fixture.scheduler.resumeRust();
+        fixture.receiver.run_pending();
+        assert_eq!(fixture.ran(), "graphics,io");
+    }
+
+    #[test]
+    fn runs_in_insertion_order_after_delays() {
+        let fixture = Fixture::new();
+        fixture.add("delayed", 50, TaskPriority::HIGH, false);
+        fixture.add("first", 0, TaskPriority::NORMAL, false);
+        fixture.add("second", 0, TaskPriority::NORMAL, true);
+        fixture.add("third", 0, TaskPriority::NORMAL, false);
+
+        fixture.receiver.run_pending();
+        assert_eq!(fixture.ran(), "first,second,third");
+
+        std::thread::sleep(Duration::from_millis(60));
+        fixture.receiver.run_pending();
+        assert_eq!(fixture.ran(), "first,second,third,delayed");
+    }
+}
@@ -0,0 +1,666 @@
+// Copyright (c) 2023 Ubique Innovation AG <https://www.ubique.ch>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context};
+use cxx::{SharedPtr, UniquePtr};
+use roxmltree::Node;
+
+use crate::bindings::impls::DefaultLoaderInterface;
+use crate::raster::{create_raster_layer, default_zoom_info, TileMatrix, TileMatrixSet};
+use crate::*;
+
+#[derive(Debug, Clone)]
+pub struct WmtsStyle {
+    pub identifier: String,
+    pub is_default: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct WmtsDimension {
+    pub identifier: String,
+    pub default: Option<String>,
+    pub values: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WmtsResourceUrl {
+    pub format: String,
+    pub template: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct WmtsLayer {
+    pub identifier: String,
+    pub title: Option<String>,
+    /// `left, bottom, right, top` in WGS84.
+    pub wgs84_bounds: Option<[f64; 4]>,
+    pub styles: Vec<WmtsStyle>,
+    pub formats: Vec<String>,
+    pub dimensions: Vec<WmtsDimension>,
+    /// Identifiers of the tile matrix sets the layer is available in.
+    pub tile_matrix_sets: Vec<String>,
+    pub resource_urls: Vec<WmtsResourceUrl>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WmtsTileMatrix {
+    pub identifier: String,
+    pub scale_denominator: f64,
+    pub top_left: (f64, f64),
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub matrix_width: i32,
+    pub matrix_height: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct WmtsTileMatrixSet {
+    pub identifier: String,
+    pub supported_crs: String,
+    pub matrices: Vec<WmtsTileMatrix>,
+}
+
+/// The parts of a WMTS `GetCapabilities` document needed to show its layers.
+#[derive(Debug, Clone)]
+pub struct WmtsCapabilities {
+    pub layers: Vec<WmtsLayer>,
+    pub tile_matrix_sets: Vec<WmtsTileMatrixSet>,
+    /// Endpoint for key-value-pair `GetTile` requests, used for layers without `ResourceURL`.
+    pub get_tile_url: Option<String>,
+}
+
+fn children<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(move |child| child.is_element() && child.tag_name().name() == name)
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &'static str) -> Option<Node<'a, 'input>> {
+    children(node, name).next()
+}
+
+fn child_text(node: Node, name: &'static str) -> Option<String> {
+    child(node, name)
+        .and_then(|child| child.text())
+        .map(|text| text.trim().to_owned())
+}
+
+fn parse_pair(text: &str) -> Option<(f64, f64)> {
+    let mut values = text.split_whitespace().map(str::parse::<f64>);
+    Some((values.next()?.ok()?, values.next()?.ok()?))
+}
+
+impl WmtsCapabilities {
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Failed to read capabilities {}", path.display()))?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Parses the document with roxmltree. The pugixml vendored with maps-core is only used
+    /// from C++ and not bridged, and a small read-only parser is all capabilities need.
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let xml = std::str::from_utf8(bytes).context("Capabilities are not valid UTF-8")?;
+        let document = roxmltree::Document::parse(xml).context("Failed to parse capabilities")?;
+        let root = document.root_element();
+        if root.tag_name().name() != "Capabilities" {
+            bail!(
+                "Not a WMTS capabilities document: <{}>",
+                root.tag_name().name()
+            );
+        }
+        let contents =
+            child(root, "Contents").ok_or_else(|| anyhow!("Capabilities have no Contents"))?;
+
+        let layers = children(contents, "Layer")
+            .map(Self::parse_layer)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let tile_matrix_sets = children(contents, "TileMatrixSet")
+            .map(Self::parse_tile_matrix_set)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let get_tile_url = root
+            .descendants()
+            .find(|node| {
+                node.tag_name().name() == "Operation" && node.attribute("name") == Some("GetTile")
+            })
+            .and_then(|operation| {
+                operation
+                    .descendants()
+                    .find(|node| node.tag_name().name() == "Get")
+            })
+            .and_then(|get| {
+                get.attributes()
+                    .find(|attribute| attribute.name() == "href")
+                    .map(|attribute| attribute.value().to_owned())
+            });
+
+        Ok(Self {
+            layers,
+            tile_matrix_sets,
+            get_tile_url,
+        })
+    }
+
+    fn parse_layer(node: Node) -> anyhow::Result<WmtsLayer> {
+        let identifier =
+            child_text(node, "Identifier").ok_or_else(|| anyhow!("Layer without Identifier"))?;
+        let wgs84_bounds = child(node, "WGS84BoundingBox").and_then(|bounds| {
+            let (left, bottom) = parse_pair(&child_text(bounds, "LowerCorner")?)?;
+            let (right, top) = parse_pair(&child_text(bounds, "UpperCorner")?)?;
+            Some([left, bottom, right, top])
+        });
+        let styles = children(node, "Style")
+            .filter_map(|style| {
+                Some(WmtsStyle {
+                    identifier: child_text(style, "Identifier")?,
+                    is_default: style.attribute("isDefault") == Some("true"),
+                })
+            })
+            .collect();
+        let formats = children(node, "Format")
+            .filter_map(|format| format.text())
+            .map(|format| format.trim().to_owned())
+            .collect();
+        let dimensions = children(node, "Dimension")
+            .filter_map(|dimension| {
+                Some(WmtsDimension {
+                    identifier: child_text(dimension, "Identifier")?,
+                    default: child_text(dimension, "Default"),
+                    values: children(dimension, "Value")
+                        .filter_map(|value| value.text())
+                        .map(|value| value.trim().to_owned())
+                        .collect(),
+                })
+            })
+            .collect();
+        let tile_matrix_sets = children(node, "TileMatrixSetLink")
+            .filter_map(|link| child_text(link, "TileMatrixSet"))
+            .collect();
+        let resource_urls = children(node, "ResourceURL")
+            .filter(|url| url.attribute("resourceType") == Some("tile"))
+            .filter_map(|url| {
+                Some(WmtsResourceUrl {
+                    format: url.attribute("format")?.to_owned(),
+                    template: url.attribute("template")?.to_owned(),
+                })
+            })
+            .collect();
+        Ok(WmtsLayer {
+            title: child_text(node, "Title"),
+            identifier,
+            wgs84_bounds,
+            styles,
+            formats,
+            dimensions,
+            tile_matrix_sets,
+            resource_urls,
+        })
+    }
+
+    fn parse_tile_matrix_set(node: Node) -> anyhow::Result<WmtsTileMatrixSet> {
+        let identifier = child_text(node, "Identifier")
+            .ok_or_else(|| anyhow!("TileMatrixSet without Identifier"))?;
+        let supported_crs = child_text(node, "SupportedCRS")
+            .ok_or_else(|| anyhow!("TileMatrixSet {identifier} without SupportedCRS"))?;
+        let matrices = children(node, "TileMatrix")
+            .map(|matrix| {
+                let number = |name: &'static str| -> anyhow::Result<f64> {
+                    child_text(matrix, name)
+                        .and_then(|text| text.parse().ok())
+                        .ok_or_else(|| anyhow!("TileMatrix in {identifier} without valid {name}"))
+                };
+                Ok(WmtsTileMatrix {
+                    identifier: child_text(matrix, "Identifier")
+                        .ok_or_else(|| anyhow!("TileMatrix in {identifier} without Identifier"))?,
+                    scale_denominator: number("ScaleDenominator")?,
+                    top_left: child_text(matrix, "TopLeftCorner")
+                        .as_deref()
+                        .and_then(parse_pair)
+                        .ok_or_else(|| {
+                            anyhow!("TileMatrix in {identifier} without TopLeftCorner")
+                        })?,
+                    tile_width: number("TileWidth")? as u32,
+                    tile_height: number("TileHeight")? as u32,
+                    matrix_width: number("MatrixWidth")? as i32,
+                    matrix_height: number("MatrixHeight")? as i32,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(WmtsTileMatrixSet {
+            identifier,
+            supported_crs,
+            matrices,
+        })
+    }
+
+    pub fn layer(&self, identifier: &str) -> Option<&WmtsLayer> {
+        self.layers
+            .iter()
+            .find(|layer| layer.identifier == identifier)
+    }
+
+    pub fn tile_matrix_set(&self, identifier: &str) -> Option<&WmtsTileMatrixSet> {
+        self.tile_matrix_sets
+            .iter()
+            .find(|set| set.identifier == identifier)
+    }
+
+    /// Configures the layer `identifier` in its first tile matrix set maps-core can display,
+    /// with the default style and dimension values.
+    pub fn layer_config(&self, identifier: &str) -> anyhow::Result<WmtsLayerConfig> {
+        let layer = self
+            .layer(identifier)
+            .ok_or_else(|| anyhow!("Capabilities have no layer {identifier}"))?;
+        let (set, crs) = layer
+            .tile_matrix_sets
+            .iter()
+            .filter_map(|set| self.tile_matrix_set(set))
+            .find_map(|set| Some((set, maps_core_crs(&set.supported_crs)?)))
+            .ok_or_else(|| {
+                anyhow!("Layer {identifier} has no tile matrix set in a supported CRS")
+            })?;
+        self.config_for(layer, set, crs)
+    }
+
+    /// Like [`WmtsCapabilities::layer_config`] with an explicit tile matrix set.
+    pub fn layer_config_in(
+        &self,
+        identifier: &str,
+        tile_matrix_set: &str,
+    ) -> anyhow::Result<WmtsLayerConfig> {
+        let layer = self
+            .layer(identifier)
+            .ok_or_else(|| anyhow!("Capabilities have no layer {identifier}"))?;
+        if !layer
+            .tile_matrix_sets
+            .iter()
+            .any(|set| set == tile_matrix_set)
+        {
+            bail!("Layer {identifier} is not available in {tile_matrix_set}");
+        }
+        let set = self
+            .tile_matrix_set(tile_matrix_set)
+            .ok_or_else(|| anyhow!("Capabilities have no tile matrix set {tile_matrix_set}"))?;
+        let crs = maps_core_crs(&set.supported_crs)
+            .ok_or_else(|| anyhow!("Unsupported CRS {}", set.supported_crs))?;
+        self.config_for(layer, set, crs)
+    }
+
+    fn config_for(
+        &self,
+        layer: &WmtsLayer,
+        set: &WmtsTileMatrixSet,
+        crs: String,
+    ) -> anyhow::Result<WmtsLayerConfig> {
+        let Some(first) = set.matrices.first() else {
+            bail!("Tile matrix set {} has no matrices", set.identifier);
+        };
+        if set.matrices.iter().any(|matrix| {
+            matrix.top_left != first.top_left || matrix.tile_width != first.tile_width
+        }) {
+            bail!(
+                "Tile matrix set {} has matrices with different origins or tile sizes",
+                set.identifier
+            );
+        }
+
+        let style = layer
+            .styles
+            .iter()
+            .find(|style| style.is_default)
+            .or(layer.styles.first())
+            .map(|style| style.identifier.clone())
+            .unwrap_or_else(|| "default".into());
+        let format = layer
+            .resource_urls
+            .first()
+            .map(|url| url.format.clone())
+            .or_else(|| layer.formats.first().cloned())
+            .ok_or_else(|| anyhow!("Layer {} has no format", layer.identifier))?;
+
+        let mut template = match layer.resource_urls.first() {
+            Some(url) => url.template.clone(),
+            None => {
+                let Some(endpoint) = &self.get_tile_url else {
+                    bail!(
+                        "Layer {} has neither a ResourceURL nor a GetTile endpoint",
+                        layer.identifier
+                    );
+                };
+                let separator = if endpoint.contains('?') { "" } else { "?" };
+                format!(
+                    "{endpoint}{separator}SERVICE=WMTS&REQUEST=GetTile&VERSION=1.0.0&LAYER={}&STYLE={{Style}}&FORMAT={format}&TILEMATRIXSET={{TileMatrixSet}}&TILEMATRIX={{TileMatrix}}&TILEROW={{TileRow}}&TILECOL={{TileCol}}",
+                    layer.identifier
+                )
+            }
+        };
+        template = template
+            .replace("{Style}", &style)
+            .replace("{TileMatrixSet}", &set.identifier);
+        for dimension in &layer.dimensions {
+            let Some(value) = dimension.default.as_ref().or(dimension.values.first()) else {
+                continue;
+            };
+            template = template.replace(&format!("{{{}}}", dimension.identifier), value);
+        }
+
+        // EPSG:4326 lists the latitude first, CRS84 and the projected systems the easting
+        let (left, top) = if swaps_axes(&set.supported_crs) {
+            (first.top_left.1, first.top_left.0)
+        } else {
+            first.top_left
+        };
+        let mut matrix_set = TileMatrixSet {
+            crs,
+            bounds: [left, top, left, top],
+            tile_size: first.tile_width,
+            matrices: set
+                .matrices
+                .iter()
+                .enumerate()
+                .map(|(index, matrix)| TileMatrix {
+                    identifier: index as i32,
+                    scale_denominator: matrix.scale_denominator,
+                    matrix_width: matrix.matrix_width,
+                    matrix_height: matrix.matrix_height,
+                    first_column: 0,
+                    first_row: 0,
+                })
+                .collect(),
+        };
+        let tile_width = matrix_set.tile_width(&matrix_set.matrices[0]);
+        matrix_set.bounds = [
+            left,
+            top - tile_width * first.matrix_height as f64,
+            left + tile_width * first.matrix_width as f64,
+            top,
+        ];
+
+        Ok(WmtsLayerConfig {
+            layer_name: layer.identifier.clone(),
+            template,
+            matrix_identifiers: set.matrices.iter().map(|m| m.identifier.clone()).collect(),
+            matrix_set,
+        })
+    }
+}
+
+/// The code at the end of a CRS name such as `urn:ogc:def:crs:EPSG::2056`.
+fn crs_code(supported_crs: &str) -> Option<&str> {
+    supported_crs
+        .rsplit(|c| c == ':' || c == '/')
+        .find(|part| !part.is_empty())
+}
+
+/// Whether coordinates in the CRS are given as latitude, longitude.
+fn swaps_axes(supported_crs: &str) -> bool {
+    crs_code(supported_crs) == Some("4326")
+}
+
+/// Maps the CRS of a tile matrix set onto the maps-core coordinate system identifier.
+fn maps_core_crs(supported_crs: &str) -> Option<String> {
+    let identifier = match crs_code(supported_crs)? {
+        "3857" | "900913" => CoordinateSystemIdentifiers::EPSG3857(),
+        "4326" | "CRS84" => CoordinateSystemIdentifiers::EPSG4326(),
+        "2056" => CoordinateSystemIdentifiers::EPSG2056(),
+        "21781" => CoordinateSystemIdentifiers::EPSG21781(),
+        _ => return None,
+    };
+    Some(identifier.to_string_lossy().into_owned())
+}
+
+/// Layer config of a WMTS layer, see [`WmtsCapabilities::layer_config`].
+pub struct WmtsLayerConfig {
+    layer_name: String,
+    /// Resource URL with everything but the tile position filled in.
+    template: String,
+    /// WMTS identifiers of the matrices, indexed by zoom level identifier.
+    matrix_identifiers: Vec<String>,
+    matrix_set: TileMatrixSet,
+}
+
+impl WmtsLayerConfig {
+    pub fn tile_matrix_set(&self) -> &TileMatrixSet {
+        &self.matrix_set
+    }
+
+    /// The URL of a tile, empty for zoom levels the tile matrix set doesn't have.
+    pub fn tile_url(&self, x: i32, y: i32, zoom: i32) -> String {
+        let Some(matrix) = usize::try_from(zoom)
+            .ok()
+            .and_then(|zoom| self.matrix_identifiers.get(zoom))
+        else {
+            log::warn!("{} has no tile matrix for zoom {zoom}", self.layer_name);
+            return String::new();
+        };
+        self.template
+            .replace("{TileMatrix}", matrix)
+            .replace("{TileRow}", &y.to_string())
+            .replace("{TileCol}", &x.to_string())
+    }
+
+    /// Wraps the config for maps-core.
+    pub fn into_wrapper(self) -> Tiled2dMapLayerConfigWrapperImpl {
+        Tiled2dMapLayerConfigWrapperImpl(Box::new(self))
+    }
+}
+
+impl Tiled2dMapLayerConfigTrait for WmtsLayerConfig {
+    fn getCoordinateSystemIdentifier(&self) -> UniquePtr<cxx::CxxString> {
+        make_string(&self.matrix_set.crs)
+    }
+
+    fn getTileUrl(&self, x: i32, y: i32, _t: i32, zoom: i32) -> UniquePtr<cxx::CxxString> {
+        make_string(&self.tile_url(x, y, zoom))
+    }
+
+    fn getZoomLevelInfos(&self) -> UniquePtr<CxxVector<Tiled2dMapZoomLevelInfo>> {
+        self.matrix_set.zoom_level_infos()
+    }
+
+    fn getZoomInfo(&self) -> UniquePtr<Tiled2dMapZoomInfo> {
+        default_zoom_info()
+    }
+
+    fn getLayerName(&self) -> UniquePtr<cxx::CxxString> {
+        make_string(&self.layer_name)
+    }
+}
+
+/// Builds a raster layer for the WMTS layer `identifier`, loaded with the default loader.
+pub fn create_wmts_raster_layer(
+    capabilities: &WmtsCapabilities,
+    identifier: &str,
+) -> anyhow::Result<(SharedPtr<LoaderInterfaceImpl>, SharedPtr<LayerInterface>)> {
+    let config = capabilities.layer_config(identifier)?;
+    create_raster_layer(
+        Box::new(config),
+        Box::new(DefaultLoaderInterface::new(false)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAPABILITIES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Capabilities xmlns="http://www.opengis.net/wmts/1.0" xmlns:ows="http://www.opengis.net/ows/1.1" xmlns:xlink="http://www.w3.org/1999/xlink" version="1.0.0">
+  <ows:OperationsMetadata>
+    <ows:Operation name="GetTile">
+      <ows:DCP><ows:HTTP><ows:Get xlink:href="https://wmts.example.com/wmts?"/></ows:HTTP></ows:DCP>
+    </ows:Operation>
+  </ows:OperationsMetadata>
+  <Contents>
+    <Layer>
+      <ows:Title>Relief</ows:Title>
+      <ows:Identifier>ch.test.relief</ows:Identifier>
+      <ows:WGS84BoundingBox>
+        <ows:LowerCorner>5.9 45.8</ows:LowerCorner>
+        <ows:UpperCorner>10.5 47.8</ows:UpperCorner>
+      </ows:WGS84BoundingBox>
+      <Style isDefault="true"><ows:Identifier>default</ows:Identifier></Style>
+      <Format>image/png</Format>
+      <Dimension>
+        <ows:Identifier>Time</ows:Identifier>
+        <Default>current</Default>
+        <Value>current</Value>
+        <Value>2020</Value>
+      </Dimension>
+      <TileMatrixSetLink><TileMatrixSet>3857_2</TileMatrixSet></TileMatrixSetLink>
+      <ResourceURL format="image/png" resourceType="tile" template="https://wmts.example.com/1.0.0/ch.test.relief/{Style}/{Time}/{TileMatrixSet}/{TileMatrix}/{TileCol}/{TileRow}.png"/>
+    </Layer>
+    <Layer>
+      <ows:Identifier>world.dark</ows:Identifier>
+      <Style><ows:Identifier>dark</ows:Identifier></Style>
+      <Style isDefault="true"><ows:Identifier>light</ows:Identifier></Style>
+      <Format>image/jpeg</Format>
+      <TileMatrixSetLink><TileMatrixSet>4326_2</TileMatrixSet></TileMatrixSetLink>
+    </Layer>
+    <TileMatrixSet>
+      <ows:Identifier>3857_2</ows:Identifier>
+      <ows:SupportedCRS>urn:ogc:def:crs:EPSG::3857</ows:SupportedCRS>
+      <TileMatrix>
+        <ows:Identifier>0</ows:Identifier>
+        <ScaleDenominator>559082264.0287178</ScaleDenominator>
+        <TopLeftCorner>-20037508.3427892 20037508.3427892</TopLeftCorner>
+        <TileWidth>256</TileWidth><TileHeight>256</TileHeight>
+        <MatrixWidth>1</MatrixWidth><MatrixHeight>1</MatrixHeight>
+      </TileMatrix>
+      <TileMatrix>
+        <ows:Identifier>1</ows:Identifier>
+        <ScaleDenominator>279541132.0143589</ScaleDenominator>
+        <TopLeftCorner>-20037508.3427892 20037508.3427892</TopLeftCorner>
+        <TileWidth>256</TileWidth><TileHeight>256</TileHeight>
+        <MatrixWidth>2</MatrixWidth><MatrixHeight>2</MatrixHeight>
+      </TileMatrix>
+    </TileMatrixSet>
+    <TileMatrixSet>
+      <ows:Identifier>4326_2</ows:Identifier>
+      <ows:SupportedCRS>urn:ogc:def:crs:EPSG::4326</ows:SupportedCRS>
+      <TileMatrix>
+        <ows:Identifier>EPSG:4326:0</ows:Identifier>
+        <ScaleDenominator>279541132.0143589</ScaleDenominator>
+        <TopLeftCorner>90 -180</TopLeftCorner>
+        <TileWidth>256</TileWidth><TileHeight>256</TileHeight>
+        <MatrixWidth>2</MatrixWidth><MatrixHeight>1</MatrixHeight>
+      </TileMatrix>
+      <TileMatrix>
+        <ows:Identifier>EPSG:4326:1</ows:Identifier>
+        <ScaleDenominator>139770566.00717944</ScaleDenominator>
+        <TopLeftCorner>90 -180</TopLeftCorner>
+        <TileWidth>256</TileWidth><TileHeight>256</TileHeight>
+        <MatrixWidth>4</MatrixWidth><MatrixHeight>2</MatrixHeight>
+      </TileMatrix>
+    </TileMatrixSet>
+  </Contents>
+</Capabilities>"#;
+
+    fn capabilities() -> WmtsCapabilities {
+        WmtsCapabilities::from_bytes(CAPABILITIES.as_bytes()).unwrap()
+    }
+
+    fn assert_bounds(actual: [f64; 4], expected: [f64; 4]) {
+        for (actual, expected) in actual.into_iter().zip(expected) {
+            assert!(
+                (actual - expected).abs() < 1e-6 * expected.abs().max(1.0),
+                "{actual} is not {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_layers() {
+        let capabilities = capabilities();
+        assert_eq!(
+            capabilities.get_tile_url.as_deref(),
+            Some("https://wmts.example.com/wmts?")
+        );
+
+        let relief = capabilities.layer("ch.test.relief").unwrap();
+        assert_eq!(relief.title.as_deref(), Some("Relief"));
+        assert_eq!(relief.wgs84_bounds, Some([5.9, 45.8, 10.5, 47.8]));
+        assert_eq!(relief.styles.len(), 1);
+        assert!(relief.styles[0].is_default);
+        assert_eq!(relief.formats, ["image/png"]);
+        assert_eq!(relief.tile_matrix_sets, ["3857_2"]);
+        assert_eq!(relief.resource_urls.len(), 1);
+        let time = &relief.dimensions[0];
+        assert_eq!(time.identifier, "Time");
+        assert_eq!(time.default.as_deref(), Some("current"));
+        assert_eq!(time.values, ["current", "2020"]);
+
+        let set = capabilities.tile_matrix_set("4326_2").unwrap();
+        assert_eq!(set.matrices.len(), 2);
+        assert_eq!(set.matrices[1].identifier, "EPSG:4326:1");
+        assert_eq!(set.matrices[1].top_left, (90.0, -180.0));
+        assert_eq!(
+            (set.matrices[1].matrix_width, set.matrices[1].matrix_height),
+            (4, 2)
+        );
+    }
+
+    #[test]
+    fn fills_resource_url_template() {
+        let config = capabilities().layer_config("ch.test.relief").unwrap();
+        assert_eq!(
+            config.tile_url(1, 0, 1),
+            "https://wmts.example.com/1.0.0/ch.test.relief/default/current/3857_2/1/1/0.png"
+        );
+        assert_bounds(
+            config.tile_matrix_set().bounds,
+            [
+                -20037508.3427892,
+                -20037508.3427892,
+                20037508.3427892,
+                20037508.3427892,
+            ],
+        );
+    }
+
+    #[test]
+    fn falls_back_to_get_tile() {
+        let config = capabilities().layer_config("world.dark").unwrap();
+        assert_eq!(
+            config.tile_url(3, 1, 1),
+            "https://wmts.example.com/wmts?SERVICE=WMTS&REQUEST=GetTile&VERSION=1.0.0\
+             &LAYER=world.dark&STYLE=light&FORMAT=image/jpeg&TILEMATRIXSET=4326_2\
+             &TILEMATRIX=EPSG:4326:1&TILEROW=1&TILECOL=3"
+        );
+    }
+
+    #[test]
+    fn swaps_epsg_4326_axes() {
+        let config = capabilities().layer_config("world.dark").unwrap();
+        let set = config.tile_matrix_set();
+        assert_eq!(
+            set.crs,
+            CoordinateSystemIdentifiers::EPSG4326().to_string_lossy()
+        );
+        assert_bounds(set.bounds, [-180.0, -90.0, 180.0, 90.0]);
+    }
+
+    #[test]
+    fn skips_unknown_zoom_levels() {
+        let config = capabilities().layer_config("ch.test.relief").unwrap();
+        assert_eq!(config.tile_url(0, 0, 2), "");
+        assert_eq!(config.tile_url(0, 0, -1), "");
+    }
+
+    #[test]
+    fn rejects_unlinked_tile_matrix_set() {
+        let capabilities = capabilities();
+        assert!(capabilities
+            .layer_config_in("world.dark", "3857_2")
+            .is_err());
+        assert!(capabilities.layer_config("missing").is_err());
+    }
+}
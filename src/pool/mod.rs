@@ -0,0 +1,631 @@
+// Copyright (c) 2023 Ubique Innovation AG <https://www.ubique.ch>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::{
+    fmt,
+    future::Future,
+    io::Cursor,
+    panic::AssertUnwindSafe,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use openmobilemaps_sys::openmobilemaps_bindings::{
+    cxx::SharedPtr, LayerInterface, RectCoord, SchedulerBuilder, UniquePtr,
+};
+use tokio::sync::{oneshot, Notify};
+
+use crate::{
+    renderer::{MapRenderer, RenderError, RenderOptions},
+    session::RenderSession,
+};
+
+/// Builds a layer on the worker thread that renders the job.
+pub type LayerFactory = Box<dyn FnOnce() -> anyhow::Result<SharedPtr<LayerInterface>> + Send>;
+
+/// A base layer that a worker keeps between jobs as long as the `key` stays the same.
+#[derive(Clone)]
+pub struct LayerSpec {
+    pub key: String,
+    pub factory: Arc<dyn Fn() -> anyhow::Result<SharedPtr<LayerInterface>> + Send + Sync>,
+}
+
+pub struct RenderJob {
+    pub bounds: UniquePtr<RectCoord>,
+    pub view_port: (usize, usize),
+    pub layers: Vec<LayerSpec>,
+    pub overlays: Vec<LayerFactory>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RenderPoolConfig {
+    pub workers: usize,
+    /// Number of jobs that may wait for a free worker before submitting fails.
+    pub queue_capacity: usize,
+    /// A worker busy with one job for longer than this is replaced.
+    pub stall_timeout: Duration,
+    pub render_options: RenderOptions,
+    /// Runtime the loader tasks of all workers run on. Without one, every worker starts a
+    /// small runtime of its own.
+    pub runtime: Option<tokio::runtime::Handle>,
+}
+
+impl Default for RenderPoolConfig {
+    fn default() -> Self {
+        Self {
+            workers: 2,
+            queue_capacity: 16,
+            stall_timeout: Duration::from_secs(60),
+            render_options: RenderOptions::default(),
+            runtime: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PoolError {
+    /// All workers are busy and the queue is full.
+    QueueFull,
+    /// The pool was shut down.
+    Closed,
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolError::QueueFull => write!(f, "render queue is full"),
+            PoolError::Closed => write!(f, "render pool is closed"),
+        }
+    }
+}
+
+impl std::error::Error for PoolError {}
+
+#[derive(Debug, Clone)]
+pub struct WorkerHealth {
+    pub id: usize,
+    pub alive: bool,
+    /// How long the worker has been working on its current job.
+    pub busy_for: Option<Duration>,
+    pub jobs_done: u64,
+}
+
+/// Renders one job on a worker thread, given the session the worker keeps between jobs and
+/// whether the caller lost interest in the frame.
+type RenderFn = dyn Fn(&mut Option<WorkerSession>, RenderJob, &dyn Fn() -> bool) -> Result<Vec<u8>, RenderError>
+    + Send
+    + Sync;
+
+/// Renders jobs in parallel on a fixed number of headless GL contexts.
+///
+/// Every worker owns its context on a dedicated OS thread. Jobs are queued on a bounded
+/// channel, and workers that hang on a job or panicked are replaced on the next submit.
+pub struct RenderPool {
+    config: RenderPoolConfig,
+    sender: SyncSender<QueuedJob>,
+    receiver: Arc<Mutex<Receiver<QueuedJob>>>,
+    workers: Mutex<Vec<Worker>>,
+    render: Arc<RenderFn>,
+    next_worker_id: AtomicU64,
+    /// Notified whenever a worker takes a job off the queue.
+    dequeued: Arc<Notify>,
+}
+
+/// Resolves to the PNG encoded frame of a submitted job.
+///
+/// Dropping the handle cancels the job and the loader tasks it started.
+pub struct RenderHandle(oneshot::Receiver<Result<Vec<u8>, RenderError>>);
+
+impl Future for RenderHandle {
+    type Output = Result<Vec<u8>, RenderError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0).poll(cx).map(|result| {
+            result.unwrap_or_else(|_| {
+                Err(anyhow!("Render worker stopped before finishing the job").into())
+            })
+        })
+    }
+}
+
+struct QueuedJob {
+    job: RenderJob,
+    reply: oneshot::Sender<Result<Vec<u8>, RenderError>>,
+}
+
+struct Worker {
+    id: usize,
+    state: Arc<WorkerState>,
+    handle: Option<JoinHandle<()>>,
+}
+
+#[derive(Default)]
+struct WorkerState {
+    busy_since: Mutex<Option<Instant>>,
+    alive: AtomicBool,
+    retired: AtomicBool,
+    jobs_done: AtomicU64,
+}
+
+impl WorkerState {
+    fn busy_for(&self) -> Option<Duration> {
+        self.busy_since.lock().ok()?.map(|since| since.elapsed())
+    }
+
+    fn set_busy(&self, busy: bool) {
+        if let Ok(mut busy_since) = self.busy_since.lock() {
+            *busy_since = busy.then(Instant::now);
+        }
+    }
+}
+
+impl RenderPool {
+    pub fn new(config: RenderPoolConfig) -> anyhow::Result<Self> {
+        let options = config.render_options.clone();
+        let runtime = config.runtime.clone();
+        Self::with_render_fn(config, move |session, job, is_cancelled| {
+            render_job(session, &options, &runtime, job, is_cancelled)
+        })
+    }
+
+    fn with_render_fn(
+        config: RenderPoolConfig,
+        render: impl Fn(
+                &mut Option<WorkerSession>,
+                RenderJob,
+                &dyn Fn() -> bool,
+            ) -> Result<Vec<u8>, RenderError>
+            + Send
+            + Sync
+            + 'static,
+    ) -> anyhow::Result<Self> {
+        let (sender, receiver) = std::sync::mpsc::sync_channel(config.queue_capacity);
+        let pool = Self {
+            config,
+            sender,
+            receiver: Arc::new(Mutex::new(receiver)),
+            workers: Mutex::new(vec![]),
+            render: Arc::new(render),
+            next_worker_id: AtomicU64::new(0),
+            dequeued: Arc::new(Notify::new()),
+        };
+        {
+            let mut workers = pool.lock_workers();
+            for _ in 0..pool.config.workers {
+                workers.push(pool.spawn_worker()?);
+            }
+        }
+        Ok(pool)
+    }
+
+    /// Renders a job and resolves to the PNG encoded frame.
+    ///
+    /// Waits without blocking the executor while the queue is full. The future is cancel
+    /// safe: dropping it removes the job from the queue or aborts the running render.
+    pub async fn render(&self, job: RenderJob) -> Result<Vec<u8>, RenderError> {
+        let mut job = job;
+        let handle = loop {
+            // registered before trying so a worker freeing a slot in between isn't missed
+            let dequeued = self.dequeued.notified();
+            match self.try_submit(job) {
+                Ok(handle) => break handle,
+                Err((PoolError::QueueFull, returned)) => {
+                    job = returned;
+                    dequeued.await;
+                }
+                Err((e, _)) => return Err(anyhow!(e).into()),
+            }
+        };
+        handle.await
+    }
+
+    /// Queues a job, failing with [`PoolError::QueueFull`] instead of waiting for space.
+    pub fn submit(&self, job: RenderJob) -> Result<RenderHandle, PoolError> {
+        self.try_submit(job).map_err(|(e, _)| e)
+    }
+
+    fn try_submit(&self, job: RenderJob) -> Result<RenderHandle, (PoolError, RenderJob)> {
+        self.check_health();
+        let (reply, handle) = oneshot::channel();
+        match self.sender.try_send(QueuedJob { job, reply }) {
+            Ok(()) => Ok(RenderHandle(handle)),
+            Err(TrySendError::Full(queued)) => Err((PoolError::QueueFull, queued.job)),
+            Err(TrySendError::Disconnected(queued)) => Err((PoolError::Closed, queued.job)),
+        }
+    }
+
+    /// Queues a job, blocking the calling thread until there is space in the queue.
+    pub fn submit_blocking(&self, job: RenderJob) -> Result<RenderHandle, PoolError> {
+        self.check_health();
+        let (reply, handle) = oneshot::channel();
+        match self.sender.send(QueuedJob { job, reply }) {
+            Ok(()) => Ok(RenderHandle(handle)),
+            Err(_) => Err(PoolError::Closed),
+        }
+    }
+
+    pub fn health(&self) -> Vec<WorkerHealth> {
+        self.lock_workers()
+            .iter()
+            .map(|worker| WorkerHealth {
+                id: worker.id,
+                alive: worker.state.alive.load(Ordering::SeqCst),
+                busy_for: worker.state.busy_for(),
+                jobs_done: worker.state.jobs_done.load(Ordering::SeqCst),
+            })
+            .collect()
+    }
+
+    /// Replaces workers that died or are stuck on a job, returns how many were replaced.
+    ///
+    /// A stuck worker can't be interrupted; it is detached and exits once its job returns.
+    pub fn check_health(&self) -> usize {
+        let mut workers = self.lock_workers();
+        let mut replaced = 0;
+        for worker in workers.iter_mut() {
+            let alive = worker.state.alive.load(Ordering::SeqCst);
+            let stalled = worker
+                .state
+                .busy_for()
+                .map_or(false, |busy_for| busy_for > self.config.stall_timeout);
+            if alive && !stalled {
+                continue;
+            }
+            log::warn!(
+                "Replacing render worker {} (alive: {alive}, stalled: {stalled})",
+                worker.id
+            );
+            match self.spawn_worker() {
+                Ok(new_worker) => {
+                    worker.state.retired.store(true, Ordering::SeqCst);
+                    let old_worker = std::mem::replace(worker, new_worker);
+                    if !alive {
+                        if let Some(handle) = old_worker.handle {
+                            let _ = handle.join();
+                        }
+                    }
+                    replaced += 1;
+                }
+                Err(e) => log::error!("Could not spawn render worker: {e}"),
+            }
+        }
+        replaced
+    }
+
+    fn spawn_worker(&self) -> anyhow::Result<Worker> {
+        let id = self.next_worker_id.fetch_add(1, Ordering::SeqCst) as usize;
+        let state = Arc::new(WorkerState::default());
+        state.alive.store(true, Ordering::SeqCst);
+        let receiver = self.receiver.clone();
+        let dequeued = self.dequeued.clone();
+        let render = self.render.clone();
+        let worker_state = state.clone();
+        let handle = std::thread::Builder::new()
+            .name(format!("render-worker-{id}"))
+            .spawn(move || run_worker(id, render, receiver, dequeued, worker_state))?;
+        Ok(Worker {
+            id,
+            state,
+            handle: Some(handle),
+        })
+    }
+
+    fn lock_workers(&self) -> std::sync::MutexGuard<'_, Vec<Worker>> {
+        self.workers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for RenderPool {
+    fn drop(&mut self) {
+        let workers = std::mem::take(&mut *self.lock_workers());
+        for worker in &workers {
+            worker.state.retired.store(true, Ordering::SeqCst);
+        }
+        // closing the queue wakes up the idle workers
+        let (sender, _) = std::sync::mpsc::sync_channel(0);
+        drop(std::mem::replace(&mut self.sender, sender));
+        for worker in workers {
+            if worker.state.busy_for().is_some() {
+                // leave stuck workers behind instead of blocking the drop
+                continue;
+            }
+            if let Some(handle) = worker.handle {
+                let _ = handle.join();
+            }
+        }
+    }
+}
+
+struct WorkerSession {
+    session: RenderSession,
+    layer_keys: Vec<String>,
+}
+
+fn run_worker(
+    id: usize,
+    render: Arc<RenderFn>,
+    receiver: Arc<Mutex<Receiver<QueuedJob>>>,
+    dequeued: Arc<Notify>,
+    state: Arc<WorkerState>,
+) {
+    log::debug!("Render worker {id} started");
+    let mut worker_session: Option<WorkerSession> = None;
+    while !state.retired.load(Ordering::SeqCst) {
+        let queued = {
+            let receiver = receiver.lock().unwrap_or_else(|e| e.into_inner());
+            match receiver.recv() {
+                Ok(queued) => queued,
+                Err(_) => break,
+            }
+        };
+        dequeued.notify_waiters();
+        if queued.reply.is_closed() {
+            continue;
+        }
+
+        state.set_busy(true);
+        let QueuedJob { job, reply } = queued;
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            render(&mut worker_session, job, &|| reply.is_closed())
+        }));
+        state.set_busy(false);
+        state.jobs_done.fetch_add(1, Ordering::SeqCst);
+
+        match result {
+            Ok(result) => {
+                let discard = matches!(
+                    result,
+                    Err(RenderError::Cancelled | RenderError::Timeout { .. })
+                );
+                let _ = reply.send(result);
+                if discard {
+                    // the scheduler may have dropped tile tasks the layers still wait for and
+                    // maps-core may still be preparing the frame, the next job gets a new map
+                    worker_session = None;
+                }
+            }
+            Err(_) => {
+                log::error!("Render worker {id} panicked");
+                let _ = reply.send(Err(anyhow!("Render worker panicked").into()));
+                break;
+            }
+        }
+    }
+    state.alive.store(false, Ordering::SeqCst);
+    log::debug!("Render worker {id} stopped");
+}
+
+fn render_job(
+    worker_session: &mut Option<WorkerSession>,
+    options: &RenderOptions,
+    runtime: &Option<tokio::runtime::Handle>,
+    job: RenderJob,
+    is_cancelled: impl Fn() -> bool,
+) -> Result<Vec<u8>, RenderError> {
+    let worker_session = match worker_session.take() {
+        Some(existing) => worker_session.insert(existing),
+        None => {
+            let mut scheduler = SchedulerBuilder::new();
+            if let Some(runtime) = runtime {
+                scheduler = scheduler.handle(runtime.clone());
+            }
+            let renderer = MapRenderer::builder(job.view_port)
+                .scheduler(scheduler)
+                .render_options(options.clone())
+                .build()?;
+            let session = RenderSession::from_renderer(renderer);
+            worker_session.insert(WorkerSession {
+                session,
+                layer_keys: vec![],
+            })
+        }
+    };
+    let session = &mut worker_session.session;
+    session.renderer_mut().set_view_port(job.view_port)?;
+
+    let layer_keys: Vec<String> = job.layers.iter().map(|spec| spec.key.clone()).collect();
+    if layer_keys != worker_session.layer_keys {
+        session.set_overlays(vec![]);
+        session.clear_base_layers();
+        worker_session.layer_keys.clear();
+        for spec in &job.layers {
+            session.add_base_layer((spec.factory)()?);
+        }
+        worker_session.layer_keys = layer_keys;
+    }
+
+    let overlays = job
+        .overlays
+        .into_iter()
+        .map(|factory| factory())
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    session.set_overlays(overlays);
+
+    let image = session.render_frame_cancellable(job.bounds, is_cancelled)?;
+    let mut encoded = vec![];
+    image
+        .write_to(
+            &mut Cursor::new(&mut encoded),
+            image::ImageOutputFormat::Png,
+        )
+        .map_err(|e| anyhow!("Could not encode frame: {e}"))?;
+    Ok(encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{atomic::AtomicUsize, mpsc};
+
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Fake jobs never touch GL, their view port tells the fake render functions what to do.
+    fn job(view_port: (usize, usize)) -> RenderJob {
+        RenderJob {
+            bounds: UniquePtr::null(),
+            view_port,
+            layers: vec![],
+            overlays: vec![],
+        }
+    }
+
+    fn config(workers: usize, queue_capacity: usize) -> RenderPoolConfig {
+        RenderPoolConfig {
+            workers,
+            queue_capacity,
+            ..Default::default()
+        }
+    }
+
+    fn resolve(handle: RenderHandle) -> Result<Vec<u8>, RenderError> {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(handle)
+    }
+
+    fn wait_until(condition: impl Fn() -> bool) {
+        let start = Instant::now();
+        while !condition() {
+            assert!(start.elapsed() < TIMEOUT, "timed out");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn is_busy(pool: &RenderPool) -> bool {
+        pool.health().iter().any(|worker| worker.busy_for.is_some())
+    }
+
+    /// A render function that blocks every job until it is released.
+    fn gated() -> (
+        mpsc::Sender<()>,
+        impl Fn(
+                &mut Option<WorkerSession>,
+                RenderJob,
+                &dyn Fn() -> bool,
+            ) -> Result<Vec<u8>, RenderError>
+            + Send
+            + Sync
+            + 'static,
+    ) {
+        let (release, gate) = mpsc::channel::<()>();
+        let gate = Mutex::new(gate);
+        let render = move |_: &mut Option<WorkerSession>, _: RenderJob, _: &dyn Fn() -> bool| {
+            let _ = gate.lock().unwrap().recv_timeout(TIMEOUT);
+            Ok(vec![1])
+        };
+        (release, render)
+    }
+
+    #[test]
+    fn rejects_jobs_when_queue_is_full() {
+        let (release, render) = gated();
+        let pool = RenderPool::with_render_fn(config(1, 1), render).unwrap();
+
+        let running = pool.submit(job((1, 1))).unwrap();
+        wait_until(|| is_busy(&pool));
+        let queued = pool.submit(job((1, 1))).unwrap();
+        assert!(matches!(
+            pool.submit(job((1, 1))),
+            Err(PoolError::QueueFull)
+        ));
+
+        release.send(()).unwrap();
+        release.send(()).unwrap();
+        assert_eq!(resolve(running).unwrap(), [1]);
+        assert_eq!(resolve(queued).unwrap(), [1]);
+    }
+
+    #[test]
+    fn cancels_jobs_of_dropped_handles() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let cancelled = Arc::new(AtomicUsize::new(0));
+        let pool = {
+            let calls = calls.clone();
+            let cancelled = cancelled.clone();
+            RenderPool::with_render_fn(config(1, 4), move |_, job, is_cancelled| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                if job.view_port == (1, 1) {
+                    let start = Instant::now();
+                    while !is_cancelled() && start.elapsed() < TIMEOUT {
+                        std::thread::sleep(Duration::from_millis(1));
+                    }
+                    cancelled.fetch_add(1, Ordering::SeqCst);
+                    return Err(RenderError::Cancelled);
+                }
+                Ok(vec![2])
+            })
+            .unwrap()
+        };
+
+        let running = pool.submit(job((1, 1))).unwrap();
+        wait_until(|| is_busy(&pool));
+        // a job dropped while it waits in the queue never runs
+        drop(pool.submit(job((2, 2))).unwrap());
+        drop(running);
+
+        assert_eq!(resolve(pool.submit(job((2, 2))).unwrap()).unwrap(), [2]);
+        assert_eq!(cancelled.load(Ordering::SeqCst), 1);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn replaces_workers_that_panicked() {
+        let pool = RenderPool::with_render_fn(config(1, 4), |_, job, _| {
+            if job.view_port == (1, 1) {
+                panic!("render failed");
+            }
+            Ok(vec![3])
+        })
+        .unwrap();
+
+        let result = resolve(pool.submit(job((1, 1))).unwrap());
+        assert!(matches!(result, Err(RenderError::Frame(_))));
+        wait_until(|| !pool.health()[0].alive);
+
+        assert_eq!(pool.check_health(), 1);
+        let health = pool.health();
+        assert_eq!(health.len(), 1);
+        assert!(health[0].alive);
+        assert_eq!(resolve(pool.submit(job((2, 2))).unwrap()).unwrap(), [3]);
+    }
+
+    #[test]
+    fn replaces_stalled_workers() {
+        let (release, render) = gated();
+        let pool = RenderPool::with_render_fn(
+            RenderPoolConfig {
+                stall_timeout: Duration::from_millis(20),
+                ..config(1, 4)
+            },
+            render,
+        )
+        .unwrap();
+
+        let stalled = pool.submit(job((1, 1))).unwrap();
+        wait_until(|| is_busy(&pool));
+        std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(pool.check_health(), 1);
+        let health = pool.health();
+        assert_eq!(health[0].id, 1);
+        assert!(health[0].busy_for.is_none());
+
+        // the detached worker still finishes its job
+        release.send(()).unwrap();
+        assert_eq!(resolve(stalled).unwrap(), [1]);
+        release.send(()).unwrap();
+        assert_eq!(resolve(pool.submit(job((2, 2))).unwrap()).unwrap(), [1]);
+    }
+}
@@ -0,0 +1,225 @@
+// Copyright (c) 2023 Ubique Innovation AG <https://www.ubique.ch>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use anyhow::bail;
+use openmobilemaps_sys::openmobilemaps_bindings::{cxx::let_cxx_string, *};
+
+/// What the next frame shows.
+pub enum View {
+    /// The area to bring into view, north-up.
+    Bounds(UniquePtr<RectCoord>),
+    /// `zoom` is the scale denominator, e.g. `25000.0` for 1:25'000 at the renderer's pixel
+    /// density. `rotation` is in degrees, clockwise.
+    Center {
+        center: Coordinate,
+        zoom: f64,
+        rotation: f32,
+    },
+}
+
+impl From<UniquePtr<RectCoord>> for View {
+    fn from(bounds: UniquePtr<RectCoord>) -> Self {
+        View::Bounds(bounds)
+    }
+}
+
+/// Zoom limits of a camera as scale denominators, `min` is the most zoomed out scale.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ZoomLimits {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl ZoomLimits {
+    pub(crate) fn new(min_zoom: f64, max_zoom: f64) -> anyhow::Result<Self> {
+        if max_zoom > min_zoom {
+            bail!("Max zoom 1:{max_zoom} is more zoomed out than min zoom 1:{min_zoom}");
+        }
+        Ok(Self {
+            min: min_zoom,
+            max: max_zoom,
+        })
+    }
+
+    pub(crate) fn clamp(&self, zoom: f64) -> f64 {
+        zoom.min(self.min).max(self.max)
+    }
+}
+
+/// The 2d camera of a map, see [`super::MapRenderer::camera`].
+///
+/// Zooms are scale denominators, so the min zoom is the most zoomed out scale and the max
+/// zoom the most zoomed in one. Center, zoom and rotation set here are what frames show
+/// while the renderer has no [`View`], the zoom limits apply to every frame.
+pub struct Camera {
+    camera: SharedPtr<MapCamera2dInterface>,
+    /// maps-core identifier of the map's coordinate system.
+    coordinate_system: String,
+}
+
+impl Camera {
+    pub(crate) fn new(
+        map_interface: &SharedPtr<MapInterface>,
+        coordinate_system: &str,
+    ) -> anyhow::Result<Self> {
+        let camera = pin_mut!(map_interface).getCamera();
+        if camera.is_null() {
+            bail!("Map has no camera");
+        }
+        Ok(Self {
+            camera,
+            coordinate_system: coordinate_system.to_owned(),
+        })
+    }
+
+    pub fn center(&self) -> anyhow::Result<Coordinate> {
+        let camera = &self.camera;
+        let center = pin_mut!(camera).getCenterPosition().within_unique_ptr();
+        Coordinate::from_coord(&center)
+    }
+
+    pub fn set_center(&self, center: &Coordinate) -> anyhow::Result<()> {
+        let center = center.to_coord_in(&self.coordinate_system)?;
+        let camera = &self.camera;
+        pin_mut!(camera).moveToCenterPosition(&center, false);
+        Ok(())
+    }
+
+    pub fn zoom(&self) -> f64 {
+        let camera = &self.camera;
+        pin_mut!(camera).getZoom()
+    }
+
+    /// Sets the zoom, clamped to the zoom limits of the camera.
+    pub fn set_zoom(&self, zoom: f64) {
+        let zoom = self.clamp_zoom(zoom);
+        let camera = &self.camera;
+        pin_mut!(camera).setZoom(zoom, false);
+    }
+
+    /// Rotation in degrees, clockwise.
+    pub fn rotation(&self) -> f32 {
+        let camera = &self.camera;
+        pin_mut!(camera).getRotation()
+    }
+
+    pub fn set_rotation(&self, rotation: f32) {
+        let camera = &self.camera;
+        pin_mut!(camera).setRotation(rotation, false);
+    }
+
+    pub fn set_zoom_limits(&self, min_zoom: f64, max_zoom: f64) -> anyhow::Result<()> {
+        let limits = ZoomLimits::new(min_zoom, max_zoom)?;
+        let camera = &self.camera;
+        pin_mut!(camera).setMinZoom(limits.min);
+        pin_mut!(camera).setMaxZoom(limits.max);
+        Ok(())
+    }
+
+    pub fn clamp_zoom(&self, zoom: f64) -> f64 {
+        let camera = &self.camera;
+        ZoomLimits {
+            min: pin_mut!(camera).getMinZoom(),
+            max: pin_mut!(camera).getMaxZoom(),
+        }
+        .clamp(zoom)
+    }
+
+    /// The north-up bounds of a `view_port` sized frame around the camera's center at its
+    /// zoom.
+    pub(crate) fn current_bounds(
+        &self,
+        view_port: (usize, usize),
+    ) -> anyhow::Result<UniquePtr<RectCoord>> {
+        let camera = &self.camera;
+        let center = pin_mut!(camera).getCenterPosition().within_unique_ptr();
+        self.bounds_around_xy(coord_x(&center), coord_y(&center), self.zoom(), view_port)
+    }
+
+    /// `bounds` in the map's coordinate system, grown or shrunk around their center if
+    /// showing them in `view_port` needs a zoom outside of the zoom limits.
+    pub(crate) fn clamp_bounds(
+        &self,
+        bounds: &RectCoord,
+        view_port: (usize, usize),
+    ) -> anyhow::Result<UniquePtr<RectCoord>> {
+        let_cxx_string!(crs = &self.coordinate_system);
+        let bounds = convert_rect(&crs, bounds);
+        if bounds.is_null() {
+            bail!("Can not convert the bounds to {}", self.coordinate_system);
+        }
+        let (top_left, bottom_right) = (rect_top_left(&bounds), rect_bottom_right(&bounds));
+        let (left, top) = (coord_x(&top_left), coord_y(&top_left));
+        let (right, bottom) = (coord_x(&bottom_right), coord_y(&bottom_right));
+        let units_per_pixel = ((right - left).abs() / view_port.0 as f64)
+            .max((top - bottom).abs() / view_port.1 as f64);
+        let zoom = units_per_pixel / self.map_units_per_pixel_and_zoom()?;
+        if self.clamp_zoom(zoom) == zoom {
+            return Ok(bounds);
+        }
+        self.bounds_around_xy((left + right) / 2.0, (top + bottom) / 2.0, zoom, view_port)
+    }
+
+    /// Map units covered by one pixel per unit of zoom.
+    pub(crate) fn map_units_per_pixel_and_zoom(&self) -> anyhow::Result<f64> {
+        let zoom = self.zoom();
+        let camera = &self.camera;
+        let map_units = pin_mut!(camera).mapUnitsFromPixels(1.0);
+        if !zoom.is_finite() || zoom <= 0.0 || map_units <= 0.0 {
+            bail!("Camera has no valid zoom");
+        }
+        Ok(map_units / zoom)
+    }
+
+    /// The north-up bounds of a `view_port` sized frame around `center` at `zoom`.
+    pub(crate) fn bounds_around(
+        &self,
+        center: &Coordinate,
+        zoom: f64,
+        view_port: (usize, usize),
+    ) -> anyhow::Result<UniquePtr<RectCoord>> {
+        let center = center.to_coord_in(&self.coordinate_system)?;
+        self.bounds_around_xy(coord_x(&center), coord_y(&center), zoom, view_port)
+    }
+
+    fn bounds_around_xy(
+        &self,
+        center_x: f64,
+        center_y: f64,
+        zoom: f64,
+        view_port: (usize, usize),
+    ) -> anyhow::Result<UniquePtr<RectCoord>> {
+        let units_per_pixel = self.clamp_zoom(zoom) * self.map_units_per_pixel_and_zoom()?;
+        let half_width = view_port.0 as f64 * units_per_pixel / 2.0;
+        let half_height = view_port.1 as f64 * units_per_pixel / 2.0;
+        let crs = self.coordinate_system.as_str();
+        Ok(RectCoord::new(
+            Coord::new(crs, center_x - half_width, center_y + half_height, 0.0).within_unique_ptr(),
+            Coord::new(crs, center_x + half_width, center_y - half_height, 0.0).within_unique_ptr(),
+        )
+        .within_unique_ptr())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_max_zoom_above_min_zoom() {
+        assert!(ZoomLimits::new(1000.0, 5000.0).is_err());
+        assert!(ZoomLimits::new(5000.0, 5000.0).is_ok());
+    }
+
+    #[test]
+    fn clamps_zoom_between_limits() {
+        let limits = ZoomLimits::new(5000.0, 1000.0).unwrap();
+        // larger scale denominators are further zoomed out
+        assert_eq!(limits.clamp(10000.0), 5000.0);
+        assert_eq!(limits.clamp(500.0), 1000.0);
+        assert_eq!(limits.clamp(2000.0), 2000.0);
+    }
+}
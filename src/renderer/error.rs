@@ -0,0 +1,104 @@
+// Copyright (c) 2023 Ubique Innovation AG <https://www.ubique.ch>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::fmt;
+
+use image::RgbaImage;
+use openmobilemaps_sys::openmobilemaps_bindings::LayerReadyState;
+
+/// Non-ready state a layer was stuck in when a frame could not be completed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailedState {
+    Error,
+    TimeoutError,
+    NotReady,
+}
+
+impl FailedState {
+    pub(crate) fn from_ready_state(state: LayerReadyState) -> Option<Self> {
+        match state {
+            LayerReadyState::READY => None,
+            LayerReadyState::ERROR => Some(Self::Error),
+            LayerReadyState::TIMEOUT_ERROR => Some(Self::TimeoutError),
+            _ => Some(Self::NotReady),
+        }
+    }
+}
+
+impl fmt::Display for FailedState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FailedState::Error => write!(f, "ERROR"),
+            FailedState::TimeoutError => write!(f, "TIMEOUT_ERROR"),
+            FailedState::NotReady => write!(f, "NOT_READY"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RenderError {
+    /// The frame could not be set up or read back.
+    Frame(anyhow::Error),
+    /// A layer reported `ERROR` while preparing the frame.
+    LayerFailed {
+        /// Index of the failing layer in the order it was added, if it could be determined.
+        layer: Option<usize>,
+        state: FailedState,
+    },
+    /// The layers were not ready before the deadline.
+    Timeout {
+        /// Index of the first layer that was not ready, if it could be determined.
+        layer: Option<usize>,
+        state: FailedState,
+        /// Whatever was drawn at the deadline, if partial frames were requested.
+        partial: Option<RgbaImage>,
+    },
+    /// The caller lost interest in the frame before it was ready.
+    Cancelled,
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::Frame(e) => write!(f, "could not render frame: {e}"),
+            RenderError::LayerFailed {
+                layer: Some(layer),
+                state,
+            } => {
+                write!(f, "layer {layer} failed with {state}")
+            }
+            RenderError::LayerFailed { layer: None, state } => {
+                write!(f, "a layer failed with {state}")
+            }
+            RenderError::Timeout {
+                layer: Some(layer),
+                state,
+                ..
+            } => write!(f, "layer {layer} was not ready in time ({state})"),
+            RenderError::Timeout {
+                layer: None, state, ..
+            } => {
+                write!(f, "layers were not ready in time ({state})")
+            }
+            RenderError::Cancelled => write!(f, "render was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RenderError::Frame(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<anyhow::Error> for RenderError {
+    fn from(e: anyhow::Error) -> Self {
+        RenderError::Frame(e)
+    }
+}
@@ -0,0 +1,195 @@
+// Copyright (c) 2023 Ubique Innovation AG <https://www.ubique.ch>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use anyhow::bail;
+use openmobilemaps_sys::openmobilemaps_bindings::*;
+
+use super::MapRenderer;
+
+/// Something on the map that has to be fully visible after fitting the camera.
+#[derive(Debug, Clone)]
+pub enum FitFeature {
+    Polyline(Vec<Coordinate>),
+    /// An icon centered on `position` that is `size` pixels wide and high on screen.
+    Icon {
+        position: Coordinate,
+        size: (f64, f64),
+    },
+}
+
+/// How [`MapRenderer::fit_bounds`] places the content in the view port.
+///
+/// Zooms are scale denominators as maps-core uses them, so `min_zoom` is the most zoomed out
+/// scale allowed and `max_zoom` the most zoomed in one.
+#[derive(Debug, Clone, Default)]
+pub struct FitOptions {
+    /// Free space in pixels between the content and the edges of the view port.
+    pub padding: f64,
+    pub min_zoom: Option<f64>,
+    pub max_zoom: Option<f64>,
+}
+
+impl MapRenderer {
+    /// Computes the bounds that show all of `features` with `options.padding` pixels around
+    /// them. The bounds have the aspect ratio of the view port and are given in the map's
+    /// coordinate system, ready for [`MapRenderer::set_bounds`].
+    pub fn fit_bounds(
+        &self,
+        features: &[FitFeature],
+        options: &FitOptions,
+    ) -> anyhow::Result<UniquePtr<RectCoord>> {
+        let crs = self.coordinate_system_identifier();
+        let mut extent: Option<[f64; 4]> = None;
+        let mut icon_margin: f64 = 0.0;
+        let mut include = |coordinate: &Coordinate| -> anyhow::Result<()> {
+            let coord = coordinate.to_coord_in(crs)?;
+            let (x, y) = (coord_x(&coord), coord_y(&coord));
+            extent = Some(match extent {
+                Some([min_x, min_y, max_x, max_y]) => {
+                    [min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y)]
+                }
+                None => [x, y, x, y],
+            });
+            Ok(())
+        };
+        for feature in features {
+            match feature {
+                FitFeature::Polyline(coordinates) => {
+                    coordinates.iter().try_for_each(&mut include)?;
+                }
+                FitFeature::Icon { position, size } => {
+                    include(position)?;
+                    icon_margin = icon_margin.max(size.0 / 2.0).max(size.1 / 2.0);
+                }
+            }
+        }
+        let Some([min_x, min_y, max_x, max_y]) = extent else {
+            bail!("Nothing to fit the camera to");
+        };
+
+        let units_per_zoom = self.camera()?.map_units_per_pixel_and_zoom()?;
+        let [left, bottom, right, top] = fit_extent(
+            [min_x, min_y, max_x, max_y],
+            self.view_port(),
+            options.padding + icon_margin,
+            units_per_zoom,
+            options,
+        )?;
+        Ok(RectCoord::new(
+            Coord::new(crs, left, top, 0.0).within_unique_ptr(),
+            Coord::new(crs, right, bottom, 0.0).within_unique_ptr(),
+        )
+        .within_unique_ptr())
+    }
+
+    /// Moves the camera of the next frame so that all of `features` are visible, see
+    /// [`MapRenderer::fit_bounds`].
+    pub fn fit_to_content(
+        &mut self,
+        features: &[FitFeature],
+        options: &FitOptions,
+    ) -> anyhow::Result<()> {
+        let bounds = self.fit_bounds(features, options)?;
+        self.set_bounds(bounds);
+        Ok(())
+    }
+}
+
+/// The `min_x, min_y, max_x, max_y` of a `view_port` sized frame that shows `extent` with
+/// `margin` pixels around it, zoomed within the limits of `options`.
+fn fit_extent(
+    extent: [f64; 4],
+    view_port: (usize, usize),
+    margin: f64,
+    units_per_zoom: f64,
+    options: &FitOptions,
+) -> anyhow::Result<[f64; 4]> {
+    let [min_x, min_y, max_x, max_y] = extent;
+    let (width, height) = (view_port.0 as f64, view_port.1 as f64);
+    let (inner_width, inner_height) = (width - 2.0 * margin, height - 2.0 * margin);
+    if inner_width <= 0.0 || inner_height <= 0.0 {
+        bail!("Padding of {margin}px leaves no room in a {width}x{height} view port");
+    }
+
+    let needed = ((max_x - min_x) / inner_width).max((max_y - min_y) / inner_height);
+    let mut zoom = needed / units_per_zoom;
+    if let Some(max_zoom) = options.max_zoom {
+        zoom = zoom.max(max_zoom);
+    }
+    if let Some(min_zoom) = options.min_zoom {
+        zoom = zoom.min(min_zoom);
+    }
+    if zoom <= 0.0 {
+        bail!("Content has no extent, set a max zoom to fit a single point");
+    }
+
+    let (center_x, center_y) = ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
+    let half_width = width * zoom * units_per_zoom / 2.0;
+    let half_height = height * zoom * units_per_zoom / 2.0;
+    Ok([
+        center_x - half_width,
+        center_y - half_height,
+        center_x + half_width,
+        center_y + half_height,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXTENT: [f64; 4] = [0.0, 0.0, 100.0, 50.0];
+    const VIEW_PORT: (usize, usize) = (300, 200);
+
+    #[test]
+    fn keeps_padding_around_content() {
+        let options = FitOptions::default();
+        // 100 by 50 units in the 200 by 100 pixels inside the padding
+        let bounds = fit_extent(EXTENT, VIEW_PORT, 50.0, 1.0, &options).unwrap();
+        assert_eq!(bounds, [-25.0, -25.0, 125.0, 75.0]);
+
+        // two map units per pixel and unit of zoom halve the zoom, not the bounds
+        let bounds = fit_extent(EXTENT, VIEW_PORT, 50.0, 2.0, &options).unwrap();
+        assert_eq!(bounds, [-25.0, -25.0, 125.0, 75.0]);
+    }
+
+    #[test]
+    fn clamps_zoom_to_options() {
+        // a zoom of 0.5 is needed, 2 is further zoomed out
+        let max_zoom = FitOptions {
+            max_zoom: Some(2.0),
+            ..Default::default()
+        };
+        let bounds = fit_extent(EXTENT, VIEW_PORT, 50.0, 1.0, &max_zoom).unwrap();
+        assert_eq!(bounds, [-250.0, -175.0, 350.0, 225.0]);
+
+        let min_zoom = FitOptions {
+            min_zoom: Some(0.25),
+            ..Default::default()
+        };
+        let bounds = fit_extent(EXTENT, VIEW_PORT, 50.0, 1.0, &min_zoom).unwrap();
+        assert_eq!(bounds, [12.5, 0.0, 87.5, 50.0]);
+    }
+
+    #[test]
+    fn needs_max_zoom_for_single_points() {
+        let point = [10.0, 20.0, 10.0, 20.0];
+        assert!(fit_extent(point, VIEW_PORT, 0.0, 1.0, &FitOptions::default()).is_err());
+
+        let options = FitOptions {
+            max_zoom: Some(1.0),
+            ..Default::default()
+        };
+        let bounds = fit_extent(point, VIEW_PORT, 0.0, 1.0, &options).unwrap();
+        assert_eq!(bounds, [-140.0, -80.0, 160.0, 120.0]);
+    }
+
+    #[test]
+    fn rejects_padding_larger_than_view_port() {
+        let result = fit_extent(EXTENT, VIEW_PORT, 100.0, 1.0, &FitOptions::default());
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,741 @@
+// Copyright (c) 2023 Ubique Innovation AG <https://www.ubique.ch>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::{
+    sync::mpsc::{Receiver, RecvTimeoutError, TryRecvError},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail};
+use euclid::Size2D;
+use image::RgbaImage;
+use surfman::{ContextAttributeFlags, ContextAttributes, GLVersion, SurfaceAccess, SurfaceType};
+
+use openmobilemaps_sys::openmobilemaps_bindings::{
+    autocxx::subclass::CppSubclass,
+    bindings::impls::{MapCallbackInterfaceImpl, MapReadyCallbackInterfaceImpl},
+    cxx::SharedPtr,
+    *,
+};
+
+mod camera;
+mod error;
+mod fit;
+pub use camera::*;
+pub use error::*;
+pub use fit::*;
+
+#[cfg(target_os = "linux")]
+use surfman::platform::unix::generic::connection::Connection;
+#[cfg(target_os = "linux")]
+use surfman::platform::unix::generic::context::Context;
+#[cfg(target_os = "linux")]
+use surfman::platform::unix::generic::device::Device;
+
+#[cfg(not(target_os = "linux"))]
+use surfman::Connection;
+#[cfg(not(target_os = "linux"))]
+use surfman::Context;
+#[cfg(not(target_os = "linux"))]
+use surfman::Device;
+
+/// How long a render waits past the map's own timeout before giving up on it.
+const DEADLINE_GRACE: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// Time the layers get to become ready before the frame fails.
+    pub timeout: Duration,
+    /// Return whatever was drawn at the deadline with [`RenderError::Timeout`].
+    pub partial_on_timeout: bool,
+    /// Pause the map between frames. Pausing releases the tile textures of the layers, keep
+    /// the map running when rendering several frames in a row.
+    pub pause_after_frame: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            partial_on_timeout: false,
+            pause_after_frame: true,
+        }
+    }
+}
+
+/// Coordinate system the map is projected in.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum CoordinateSystem {
+    /// Web mercator, the default.
+    #[default]
+    Epsg3857,
+    /// Swiss LV95.
+    Epsg2056,
+    /// WGS84 longitude and latitude.
+    Epsg4326,
+    /// Any other system, layers in it are only shown if they use the same `identifier`.
+    Custom {
+        identifier: String,
+        /// `min_x, min_y, max_x, max_y` in the system's units.
+        bounds: [f64; 4],
+        /// Length of one unit of the system in meters.
+        unit_to_screen_meter_factor: f32,
+    },
+}
+
+impl CoordinateSystem {
+    /// The maps-core identifier of the system.
+    pub fn identifier(&self) -> String {
+        match self {
+            CoordinateSystem::Epsg3857 => CoordinateSystemIdentifiers::EPSG3857()
+                .to_string_lossy()
+                .into_owned(),
+            CoordinateSystem::Epsg2056 => CoordinateSystemIdentifiers::EPSG2056()
+                .to_string_lossy()
+                .into_owned(),
+            CoordinateSystem::Epsg4326 => CoordinateSystemIdentifiers::EPSG4326()
+                .to_string_lossy()
+                .into_owned(),
+            CoordinateSystem::Custom { identifier, .. } => identifier.clone(),
+        }
+    }
+
+    fn map_coordinate_system(&self) -> UniquePtr<MapCoordinateSystem> {
+        match self {
+            CoordinateSystem::Epsg3857 => {
+                CoordinateSystemFactory::getEpsg3857System().within_unique_ptr()
+            }
+            CoordinateSystem::Epsg2056 => {
+                CoordinateSystemFactory::getEpsg2056System().within_unique_ptr()
+            }
+            CoordinateSystem::Epsg4326 => {
+                CoordinateSystemFactory::getEpsg4326System().within_unique_ptr()
+            }
+            CoordinateSystem::Custom {
+                identifier,
+                bounds: [min_x, min_y, max_x, max_y],
+                unit_to_screen_meter_factor,
+            } => {
+                let bounds = RectCoord::new(
+                    Coord::new(identifier.as_str(), *min_x, *max_y, 0.0).within_unique_ptr(),
+                    Coord::new(identifier.as_str(), *max_x, *min_y, 0.0).within_unique_ptr(),
+                )
+                .within_unique_ptr();
+                MapCoordinateSystem::new(identifier.as_str(), bounds, *unit_to_screen_meter_factor)
+                    .within_unique_ptr()
+            }
+        }
+    }
+}
+
+/// Owns a headless OpenGL context together with the maps-core map drawing into it.
+///
+/// The context is made current on the thread calling [`MapRenderer::new`], so the renderer
+/// has to stay on that thread. All GL and C++ resources are released on drop.
+pub struct MapRenderer {
+    view_port: (usize, usize),
+    device: Device,
+    context: Context,
+    map_interface: SharedPtr<MapInterface>,
+    /// maps-core identifier of the map's coordinate system.
+    coordinate_system: String,
+    task_receiver: TaskReceiver,
+    invalidate_receiver: Receiver<()>,
+    ready_state_interface: SharedPtr<MapReadyCallbackInterface>,
+    ready_state_receiver: Receiver<LayerReadyState>,
+    layers: Vec<SharedPtr<LayerInterface>>,
+    view: Option<View>,
+    options: RenderOptions,
+    pixel_density: f32,
+    /// Signals the end of a `drawReadyFrame` call that outlived its render.
+    pending_draw: Option<Receiver<()>>,
+}
+
+/// Configures a [`MapRenderer`] before its context and map are created.
+pub struct MapRendererBuilder {
+    view_port: (usize, usize),
+    scheduler: SchedulerBuilder,
+    options: RenderOptions,
+    coordinate_system: CoordinateSystem,
+    pixel_density: f32,
+}
+
+impl MapRendererBuilder {
+    /// Sets where the loader and computation tasks of the map run.
+    pub fn scheduler(mut self, scheduler: SchedulerBuilder) -> Self {
+        self.scheduler = scheduler;
+        self
+    }
+
+    pub fn render_options(mut self, options: RenderOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets the coordinate system the map is projected in. Layers in other systems are
+    /// converted by maps-core where it has a converter.
+    pub fn coordinate_system(mut self, coordinate_system: CoordinateSystem) -> Self {
+        self.coordinate_system = coordinate_system;
+        self
+    }
+
+    /// Sets the screen density in pixels per inch that maps-core converts zooms into scales
+    /// with, 1.0 by default.
+    pub fn pixel_density(mut self, pixel_density: f32) -> Self {
+        self.pixel_density = pixel_density;
+        self
+    }
+
+    pub fn build(self) -> anyhow::Result<MapRenderer> {
+        if !self.pixel_density.is_finite() || self.pixel_density <= 0.0 {
+            bail!("Invalid pixel density {}", self.pixel_density);
+        }
+        let view_port = self.view_port;
+        let (mut device, mut context) = setup_opengl(view_port)?;
+        match setup_map(
+            view_port,
+            self.scheduler,
+            &self.coordinate_system,
+            self.pixel_density,
+        ) {
+            Ok(map) => Ok(MapRenderer {
+                view_port,
+                device,
+                context,
+                map_interface: map.map_interface,
+                coordinate_system: map.coordinate_system,
+                task_receiver: map.task_receiver,
+                invalidate_receiver: map.invalidate_receiver,
+                ready_state_interface: map.ready_state_interface,
+                ready_state_receiver: map.ready_state_receiver,
+                layers: vec![],
+                view: None,
+                options: self.options,
+                pixel_density: self.pixel_density,
+                pending_draw: None,
+            }),
+            Err(e) => {
+                destroy_opengl(&mut device, &mut context);
+                Err(e)
+            }
+        }
+    }
+}
+
+impl MapRenderer {
+    pub fn new(view_port: (usize, usize)) -> anyhow::Result<Self> {
+        Self::builder(view_port).build()
+    }
+
+    pub fn builder(view_port: (usize, usize)) -> MapRendererBuilder {
+        MapRendererBuilder {
+            view_port,
+            scheduler: SchedulerBuilder::new(),
+            options: RenderOptions::default(),
+            coordinate_system: CoordinateSystem::default(),
+            pixel_density: 1.0,
+        }
+    }
+
+    pub fn view_port(&self) -> (usize, usize) {
+        self.view_port
+    }
+
+    /// Replaces the drawing surface with one of the new size, keeping the map and its layers.
+    pub fn set_view_port(&mut self, view_port: (usize, usize)) -> anyhow::Result<()> {
+        if view_port == self.view_port {
+            return Ok(());
+        }
+        if self.device.make_context_current(&self.context).is_err() {
+            bail!("Could not make context current");
+        }
+        let Ok(mut surface) = self.device.create_surface(
+            &self.context,
+            SurfaceAccess::GPUOnly,
+            SurfaceType::Generic {
+                size: Size2D::new(view_port.0 as i32, view_port.1 as i32),
+            },
+        ) else {
+            bail!("Failed to create drawing surface");
+        };
+        match self.device.unbind_surface_from_context(&mut self.context) {
+            Ok(Some(mut old_surface)) => {
+                let _ = self
+                    .device
+                    .destroy_surface(&mut self.context, &mut old_surface);
+            }
+            Ok(None) => {}
+            Err(_) => {
+                let _ = self.device.destroy_surface(&mut self.context, &mut surface);
+                bail!("Could not unbind the previous surface");
+            }
+        }
+        if let Err((_, mut surface)) = self
+            .device
+            .bind_surface_to_context(&mut self.context, surface)
+        {
+            let _ = self.device.destroy_surface(&mut self.context, &mut surface);
+            bail!("Could not bind surface to context");
+        }
+        bind_framebuffer(&self.device, &self.context, view_port)?;
+
+        self.view_port = view_port;
+        let map_interface = &self.map_interface;
+        pin_mut!(map_interface).setViewportSize(
+            &Vec2I::new(view_port.0 as i32, view_port.1 as i32).within_unique_ptr(),
+        );
+        Ok(())
+    }
+
+    pub fn map_interface(&self) -> &SharedPtr<MapInterface> {
+        &self.map_interface
+    }
+
+    pub fn coordinate_system_identifier(&self) -> &str {
+        &self.coordinate_system
+    }
+
+    pub fn pixel_density(&self) -> f32 {
+        self.pixel_density
+    }
+
+    pub fn camera(&self) -> anyhow::Result<Camera> {
+        Camera::new(&self.map_interface, &self.coordinate_system)
+    }
+
+    /// Builds a raster layer from `config` and `loader` and adds it to the map.
+    ///
+    /// Fails if the map can not show tiles in the coordinate system of `config`.
+    pub fn add_raster_layer(
+        &mut self,
+        config: Box<dyn Tiled2dMapLayerConfigTrait>,
+        loader: Box<dyn LoaderInterfaceTrait>,
+    ) -> anyhow::Result<SharedPtr<LayerInterface>> {
+        let layer_crs = config
+            .getCoordinateSystemIdentifier()
+            .to_string_lossy()
+            .into_owned();
+        raster::check_crs_compatible(&layer_crs, &self.coordinate_system)?;
+        let (_loader, layer) = raster::create_raster_layer(config, loader)?;
+        self.add_layer(layer.clone());
+        Ok(layer)
+    }
+
+    pub fn add_layer(&mut self, layer: SharedPtr<LayerInterface>) {
+        let map_interface = &self.map_interface;
+        pin_mut!(map_interface).addLayer(&layer);
+        self.layers.push(layer);
+    }
+
+    /// Removes a layer previously added with [`MapRenderer::add_layer`].
+    pub fn remove_layer(&mut self, layer: &SharedPtr<LayerInterface>) {
+        let Some(index) = self.layers.iter().position(|l| {
+            l.as_ref().map(|l| l as *const _) == layer.as_ref().map(|l| l as *const _)
+        }) else {
+            return;
+        };
+        let layer = self.layers.remove(index);
+        let map_interface = &self.map_interface;
+        pin_mut!(map_interface).removeLayer(&layer);
+    }
+
+    /// Sets the area that the next call to [`MapRenderer::render`] brings into view.
+    pub fn set_bounds(&mut self, bounds: UniquePtr<RectCoord>) {
+        self.view = Some(View::Bounds(bounds));
+    }
+
+    /// Sets what the next call to [`MapRenderer::render`] shows, either bounds or a center
+    /// with zoom and rotation.
+    pub fn set_view(&mut self, view: impl Into<View>) {
+        self.view = Some(view.into());
+    }
+
+    /// Lets the next frames show what the [`Camera`] is set to instead of a view.
+    pub fn clear_view(&mut self) {
+        self.view = None;
+    }
+
+    /// Returns whether maps-core asked for a redraw since the last call.
+    pub fn is_invalidated(&self) -> bool {
+        self.invalidate_receiver.try_iter().count() > 0
+    }
+
+    pub fn render_options(&self) -> &RenderOptions {
+        &self.options
+    }
+
+    pub fn set_render_options(&mut self, options: RenderOptions) {
+        self.options = options;
+    }
+
+    /// Draws a frame once all layers report ready and reads it back from the GPU.
+    ///
+    /// Fails if a layer reports an error or the layers are not ready within
+    /// [`RenderOptions::timeout`].
+    pub fn render(&mut self) -> Result<RgbaImage, RenderError> {
+        self.render_cancellable(|| false)
+    }
+
+    /// Like [`MapRenderer::render`], but gives up with [`RenderError::Cancelled`] as soon as
+    /// `is_cancelled` returns true. Outstanding loader tasks are then dropped from the scheduler,
+    /// so the layers may miss tiles until they are added again.
+    ///
+    /// After a cancel or timeout maps-core keeps preparing the frame in the background, see
+    /// [`MapRenderer::is_busy`].
+    pub fn render_cancellable(
+        &mut self,
+        is_cancelled: impl Fn() -> bool,
+    ) -> Result<RgbaImage, RenderError> {
+        if let Some(done) = self.pending_draw.take() {
+            // a frame that became ready may still be finishing up
+            if let Err(RecvTimeoutError::Timeout) = done.recv_timeout(DEADLINE_GRACE) {
+                self.pending_draw = Some(done);
+                return Err(anyhow!("The previous frame is still being prepared").into());
+            }
+        }
+        let camera = self.camera()?;
+        let (bounds, rotation) = match &self.view {
+            Some(View::Bounds(bounds)) => (camera.clamp_bounds(bounds, self.view_port)?, 0.0),
+            Some(View::Center {
+                center,
+                zoom,
+                rotation,
+            }) => (
+                camera.bounds_around(center, *zoom, self.view_port)?,
+                *rotation,
+            ),
+            // without a view the frame shows what the camera was set to
+            None => (camera.current_bounds(self.view_port)?, camera.rotation()),
+        };
+        let view_port = self.view_port;
+        let options = self.options.clone();
+        let map_interface = &self.map_interface;
+
+        if self.device.make_context_current(&self.context).is_err() {
+            return Err(anyhow!("Could not make context current").into());
+        }
+
+        // drop states left over from an earlier frame
+        self.ready_state_receiver.try_iter().for_each(drop);
+
+        pin_mut!(map_interface).resume();
+        pin_mut!(map_interface).setViewportSize(
+            &Vec2I::new(view_port.0 as i32, view_port.1 as i32).within_unique_ptr(),
+        );
+        // drawReadyFrame only moves the camera to the bounds and keeps its rotation
+        camera.set_rotation(rotation);
+        pin_mut!(map_interface).invalidate();
+        pin_mut!(map_interface).drawFrame();
+
+        // maps-core enforces the timeout itself, the deadline only guards against it never
+        // reporting back
+        let timeout = options.timeout;
+        let deadline = Instant::now() + timeout + DEADLINE_GRACE;
+
+        // drawReadyFrame blocks until the layers are ready, so it runs on a thread that is
+        // left behind when the frame is given up on
+        let (done_sender, done) = std::sync::mpsc::channel();
+        let draw_map_interface = map_interface.clone();
+        let ready_state_interface = self.ready_state_interface.clone();
+        std::thread::spawn(move || {
+            let map_interface = &draw_map_interface;
+            pin_mut!(map_interface).drawReadyFrame(
+                &bounds,
+                timeout.as_secs_f32(),
+                &ready_state_interface,
+            );
+            let _ = done_sender.send(());
+        });
+
+        let mut drawing = true;
+        let result = 'frame: loop {
+            if is_cancelled() {
+                let scheduler = pin_mut!(map_interface).getScheduler();
+                pin_mut!(scheduler).clear();
+                break Err(RenderError::Cancelled);
+            }
+
+            pin_mut!(map_interface).drawFrame();
+            self.task_receiver.run_pending();
+
+            // checked before the states so that a state sent right before returning is seen
+            drawing = drawing && matches!(done.try_recv(), Err(TryRecvError::Empty));
+            let mut failed = None;
+            for state in self.ready_state_receiver.try_iter() {
+                match FailedState::from_ready_state(state) {
+                    None => {
+                        pin_mut!(map_interface).drawFrame();
+                        self.task_receiver.run_pending();
+                        break 'frame read_frame(view_port).map_err(RenderError::from);
+                    }
+                    Some(FailedState::NotReady) => {}
+                    Some(state) => failed = Some(state),
+                }
+            }
+            if failed.is_none() && (!drawing || Instant::now() > deadline) {
+                failed = Some(FailedState::NotReady);
+            }
+
+            match failed {
+                Some(FailedState::Error) => {
+                    break Err(RenderError::LayerFailed {
+                        layer: self.first_unready_layer(),
+                        state: FailedState::Error,
+                    });
+                }
+                Some(state) => {
+                    let partial = if options.partial_on_timeout {
+                        pin_mut!(map_interface).drawFrame();
+                        read_frame(view_port).ok()
+                    } else {
+                        None
+                    };
+                    break Err(RenderError::Timeout {
+                        layer: self.first_unready_layer(),
+                        state,
+                        partial,
+                    });
+                }
+                None => {}
+            }
+
+            std::thread::yield_now();
+            std::thread::sleep(Duration::from_millis(10));
+        };
+
+        if options.pause_after_frame {
+            pin_mut!(map_interface).pause();
+        }
+        if drawing {
+            self.pending_draw = Some(done);
+        }
+        if let Err(e) = &result {
+            log::warn!("{e}");
+        }
+        result
+    }
+
+    /// Returns whether maps-core is still preparing a frame that was cancelled or timed
+    /// out. Rendering fails until it is done, which takes at most the frame's timeout.
+    pub fn is_busy(&self) -> bool {
+        self.pending_draw.as_ref().map_or(false, |done| {
+            matches!(done.try_recv(), Err(TryRecvError::Empty))
+        })
+    }
+
+    fn first_unready_layer(&self) -> Option<usize> {
+        self.layers
+            .iter()
+            .position(|layer| pin_mut!(layer).isReadyToRenderOffscreen() != LayerReadyState::READY)
+    }
+}
+
+fn read_frame(view_port: (usize, usize)) -> anyhow::Result<RgbaImage> {
+    let mut buffer = vec![0u8; view_port.0 * view_port.1 * 4];
+    unsafe {
+        gl::Finish();
+        gl::ReadPixels(
+            0,
+            0,
+            view_port.0 as i32,
+            view_port.1 as i32,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            buffer.as_mut_ptr() as _,
+        );
+    }
+    let Some(mut image) = RgbaImage::from_raw(view_port.0 as u32, view_port.1 as u32, buffer)
+    else {
+        bail!("Frame buffer does not match the view port");
+    };
+    // OpenGL reads rows bottom up
+    image::imageops::flip_vertical_in_place(&mut image);
+    Ok(image)
+}
+
+impl Drop for MapRenderer {
+    fn drop(&mut self) {
+        if let Some(done) = self.pending_draw.take() {
+            let _ = done.recv_timeout(self.options.timeout + DEADLINE_GRACE);
+        }
+        let _ = self.device.make_context_current(&self.context);
+        let map_interface = &self.map_interface;
+        for layer in self.layers.drain(..) {
+            pin_mut!(map_interface).removeLayer(&layer);
+        }
+        pin_mut!(map_interface).pause();
+        pin_mut!(map_interface).destroy();
+        self.task_receiver.run_pending();
+        destroy_opengl(&mut self.device, &mut self.context);
+    }
+}
+
+struct MapData {
+    task_receiver: TaskReceiver,
+    map_interface: SharedPtr<MapInterface>,
+    coordinate_system: String,
+    invalidate_receiver: Receiver<()>,
+    ready_state_interface: SharedPtr<MapReadyCallbackInterface>,
+    ready_state_receiver: Receiver<LayerReadyState>,
+}
+
+fn setup_opengl(view_port: (usize, usize)) -> anyhow::Result<(Device, Context)> {
+    let Ok(connection) = Connection::new() else {
+        bail!("Failed to setup connection to display");
+    };
+    let Ok(adapter) = connection.create_adapter() else {
+        bail!("Failed to find suitable adapter");
+    };
+    let Ok(mut device) = connection.create_device(&adapter) else {
+        bail!("Failed to create device");
+    };
+    let context_attributes = ContextAttributes {
+        version: GLVersion::new(4, 3),
+        flags: ContextAttributeFlags::ALPHA
+            | ContextAttributeFlags::STENCIL
+            | ContextAttributeFlags::DEPTH,
+    };
+    let Ok(context_descriptor) = device.create_context_descriptor(&context_attributes) else {
+        bail!("Failed to create context descriptor");
+    };
+    let Ok(mut context) = device.create_context(&context_descriptor, None) else {
+        bail!("Failed to create context");
+    };
+
+    let Ok(surface) = device.create_surface(
+        &context,
+        SurfaceAccess::GPUOnly,
+        SurfaceType::Generic {
+            size: Size2D::new(view_port.0 as i32, view_port.1 as i32),
+        },
+    ) else {
+        let _ = device.destroy_context(&mut context);
+        bail!("Failed to create drawing surface");
+    };
+    if device
+        .bind_surface_to_context(&mut context, surface)
+        .is_err()
+    {
+        let _ = device.destroy_context(&mut context);
+        bail!("Could not bind surface to context");
+    }
+
+    if device.make_context_current(&context).is_err() {
+        destroy_opengl(&mut device, &mut context);
+        bail!("Could not make context current");
+    }
+    log::debug!("Load GL pointers");
+    gl::load_with(|s| device.get_proc_address(&context, s) as *const std::os::raw::c_void);
+
+    let mut arrays = 0;
+    log::debug!("Setup VBO");
+    unsafe { gl::GenVertexArrays(1, &mut arrays) };
+    unsafe { gl::BindVertexArray(arrays) };
+
+    log::debug!("Clear flags");
+    unsafe {
+        gl::Disable(gl::CULL_FACE);
+        gl::Disable(gl::DEPTH_TEST);
+        gl::Disable(gl::BLEND);
+        // gl::Enable(gl::MULTISAMPLE);
+    }
+    if let Err(e) = bind_framebuffer(&device, &context, view_port) {
+        destroy_opengl(&mut device, &mut context);
+        return Err(e);
+    }
+
+    Ok((device, context))
+}
+
+fn bind_framebuffer(
+    device: &Device,
+    context: &Context,
+    view_port: (usize, usize),
+) -> anyhow::Result<()> {
+    log::debug!("Bind framebuffer");
+    let Ok(Some(surface_info)) = device.context_surface_info(context) else {
+        bail!("Failed to get surface info");
+    };
+    unsafe {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, surface_info.framebuffer_object);
+        log::debug!("Set viewport");
+        gl::Viewport(0, 0, view_port.0 as i32, view_port.1 as i32);
+    }
+    Ok(())
+}
+
+fn destroy_opengl(device: &mut Device, context: &mut Context) {
+    if let Ok(Some(mut surface)) = device.unbind_surface_from_context(context) {
+        let _ = device.destroy_surface(context, &mut surface);
+    }
+    let _ = device.destroy_context(context);
+}
+
+fn setup_map(
+    view_port: (usize, usize),
+    scheduler: SchedulerBuilder,
+    coordinate_system: &CoordinateSystem,
+    pixel_density: f32,
+) -> anyhow::Result<MapData> {
+    let coordsystem = coordinate_system.map_coordinate_system();
+    if coordsystem.is_null() {
+        bail!("Could not create map coordinate system");
+    }
+    let map_config = MapConfig::new(coordsystem).within_unique_ptr();
+    if map_config.is_null() {
+        bail!("Could not create map config");
+    }
+    let (scheduler, task_receiver) = scheduler.build()?;
+    let scheduler = Box::new(scheduler);
+
+    let scheduler = unsafe { SchedulerInterfaceStaticWrapper::new1(Box::into_raw(scheduler) as _) }
+        .within_unique_ptr();
+    if scheduler.is_null() {
+        bail!("Could not initialize schedulerinterface");
+    }
+
+    let scheduler = transform_unique(scheduler);
+    let map_interface: SharedPtr<MapInterface> =
+        MapInterface::createWithOpenGl(&map_config, &scheduler, pixel_density);
+    if map_interface.is_null() {
+        bail!("Could not create map interface");
+    }
+
+    let (invalidate_sender, invalidate_receiver) = std::sync::mpsc::channel();
+    let mut callbacks = MapCallbackInterfaceImpl::default();
+    callbacks.sender = Some(invalidate_sender);
+    let callbacks = MapCallbackInterfaceImpl::new_cpp_owned(callbacks);
+    if callbacks.is_null() {
+        bail!("Could not initialize map callbacks");
+    }
+    let callback_interface =
+        MapCallbackInterfaceImpl::as_MapCallbackInterface_unique_ptr(callbacks);
+    pin_mut!(map_interface).setCallbackHandler(&to_map_callback_interface_shared_pointer(
+        callback_interface,
+    ));
+
+    let (ready_state_sender, ready_state_receiver) = std::sync::mpsc::channel();
+    let mut ready_state = MapReadyCallbackInterfaceImpl::default();
+    ready_state.sender = Some(ready_state_sender);
+    let ready_state = MapReadyCallbackInterfaceImpl::new_cpp_owned(ready_state);
+    if ready_state.is_null() {
+        bail!("Callback interface was unexpectedly null");
+    }
+    let ready_state_interface = transform_ready_state(
+        MapReadyCallbackInterfaceImpl::as_MapReadyCallbackInterface_unique_ptr(ready_state),
+    );
+
+    pin_mut!(map_interface)
+        .setViewportSize(&Vec2I::new(view_port.0 as i32, view_port.1 as i32).within_unique_ptr());
+    Ok(MapData {
+        task_receiver,
+        map_interface,
+        coordinate_system: coordinate_system.identifier(),
+        invalidate_receiver,
+        ready_state_interface,
+        ready_state_receiver,
+    })
+}
@@ -0,0 +1,130 @@
+// Copyright (c) 2023 Ubique Innovation AG <https://www.ubique.ch>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::time::{Duration, Instant};
+
+use image::RgbaImage;
+use openmobilemaps_sys::openmobilemaps_bindings::{cxx::SharedPtr, LayerInterface};
+
+use crate::renderer::{MapRenderer, RenderError, RenderOptions, View};
+
+/// Renders many frames from one map.
+///
+/// The GL context, the compiled shader programs and the base layers including their loaded
+/// tiles stay alive between frames. A frame only moves the camera and swaps the overlays.
+pub struct RenderSession {
+    renderer: MapRenderer,
+    base_layers: Vec<SharedPtr<LayerInterface>>,
+    overlays: Vec<SharedPtr<LayerInterface>>,
+    stats: FrameStats,
+}
+
+/// Timings of the frames rendered by a [`RenderSession`].
+#[derive(Debug, Clone, Default)]
+pub struct FrameStats {
+    pub frames: u64,
+    pub failed_frames: u64,
+    /// Duration of the first frame, which includes loading the tiles into a cold map.
+    pub first_frame: Option<Duration>,
+    pub last_frame: Option<Duration>,
+    pub total: Duration,
+}
+
+impl FrameStats {
+    /// Mean duration of the frames after the first one.
+    pub fn warm_average(&self) -> Option<Duration> {
+        let warm_frames = self.frames.checked_sub(1).filter(|n| *n > 0)?;
+        let warm_total = self.total - self.first_frame.unwrap_or_default();
+        Some(warm_total / warm_frames as u32)
+    }
+
+    fn record(&mut self, duration: Duration, failed: bool) {
+        if self.first_frame.is_none() {
+            self.first_frame = Some(duration);
+        }
+        self.frames += 1;
+        if failed {
+            self.failed_frames += 1;
+        }
+        self.last_frame = Some(duration);
+        self.total += duration;
+    }
+}
+
+impl RenderSession {
+    pub fn new(view_port: (usize, usize)) -> anyhow::Result<Self> {
+        Ok(Self::from_renderer(MapRenderer::new(view_port)?))
+    }
+
+    pub fn from_renderer(mut renderer: MapRenderer) -> Self {
+        let options = RenderOptions {
+            pause_after_frame: false,
+            ..renderer.render_options().clone()
+        };
+        renderer.set_render_options(options);
+        Self {
+            renderer,
+            base_layers: vec![],
+            overlays: vec![],
+            stats: FrameStats::default(),
+        }
+    }
+
+    /// Adds a layer that stays on the map for every following frame.
+    pub fn add_base_layer(&mut self, layer: SharedPtr<LayerInterface>) {
+        self.renderer.add_layer(layer.clone());
+        self.base_layers.push(layer);
+    }
+
+    pub fn clear_base_layers(&mut self) {
+        for layer in self.base_layers.drain(..) {
+            self.renderer.remove_layer(&layer);
+        }
+    }
+
+    /// Replaces the overlays drawn on top of the base layers.
+    pub fn set_overlays(&mut self, overlays: Vec<SharedPtr<LayerInterface>>) {
+        for overlay in self.overlays.drain(..) {
+            self.renderer.remove_layer(&overlay);
+        }
+        for overlay in &overlays {
+            self.renderer.add_layer(overlay.clone());
+        }
+        self.overlays = overlays;
+    }
+
+    /// Moves the camera to `view`, bounds or a center and zoom, and renders the frame.
+    pub fn render_frame(&mut self, view: impl Into<View>) -> Result<RgbaImage, RenderError> {
+        self.render_frame_cancellable(view, || false)
+    }
+
+    /// See [`MapRenderer::render_cancellable`].
+    pub fn render_frame_cancellable(
+        &mut self,
+        view: impl Into<View>,
+        is_cancelled: impl Fn() -> bool,
+    ) -> Result<RgbaImage, RenderError> {
+        let start = Instant::now();
+        self.renderer.set_view(view);
+        let result = self.renderer.render_cancellable(is_cancelled);
+        let duration = start.elapsed();
+        self.stats.record(duration, result.is_err());
+        log::debug!("frame {} rendered in {duration:?}", self.stats.frames);
+        result
+    }
+
+    pub fn stats(&self) -> &FrameStats {
+        &self.stats
+    }
+
+    pub fn renderer(&self) -> &MapRenderer {
+        &self.renderer
+    }
+
+    pub fn renderer_mut(&mut self) -> &mut MapRenderer {
+        &mut self.renderer
+    }
+}
@@ -0,0 +1,125 @@
+// Copyright (c) 2023 Ubique Innovation AG <https://www.ubique.ch>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Offline tile sources for the renderer tests.
+
+#![allow(dead_code)]
+
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use image::{Rgba, RgbaImage};
+use openmobilemaps_rs::openmobilemaps_sys::openmobilemaps_bindings::{
+    bindings::impls::DefaultLoaderInterface,
+    cxx, make_string,
+    raster::{default_zoom_info, TileMatrixSet},
+    CacheEntry, CxxVector, DecodedTileCache, TileCache, Tiled2dMapLayerConfigTrait,
+    Tiled2dMapZoomInfo, Tiled2dMapZoomLevelInfo, UniquePtr,
+};
+
+pub const TILE_COLOR: Rgba<u8> = Rgba([255, 0, 0, 255]);
+
+/// Serves the same single colored tile for every URL, so that no test goes to the network.
+pub struct SolidTiles(Vec<u8>);
+
+impl SolidTiles {
+    pub fn new(color: Rgba<u8>) -> Arc<Self> {
+        let mut png = Vec::new();
+        RgbaImage::from_pixel(256, 256, color)
+            .write_to(&mut Cursor::new(&mut png), image::ImageOutputFormat::Png)
+            .unwrap();
+        Arc::new(Self(png))
+    }
+}
+
+impl TileCache for SolidTiles {
+    fn get(&self, _url: &str) -> Option<CacheEntry> {
+        Some(CacheEntry {
+            data: self.0.clone(),
+            etag: None,
+            expires: None,
+        })
+    }
+
+    fn put(&self, _url: &str, _entry: &CacheEntry) {}
+
+    fn touch(&self, _url: &str, _expires: Option<SystemTime>) {}
+}
+
+/// Serves tiles whose color depends on their URL, so that misplaced tiles show up.
+pub struct PatternTiles;
+
+impl TileCache for PatternTiles {
+    fn get(&self, url: &str) -> Option<CacheEntry> {
+        let hash = url.bytes().fold(17u32, |hash, byte| {
+            hash.wrapping_mul(31).wrapping_add(byte as u32)
+        });
+        let [r, g, b, _] = hash.to_le_bytes();
+        let mut png = Vec::new();
+        RgbaImage::from_pixel(256, 256, Rgba([r, g, b, 255]))
+            .write_to(&mut Cursor::new(&mut png), image::ImageOutputFormat::Png)
+            .unwrap();
+        Some(CacheEntry {
+            data: png,
+            etag: None,
+            expires: None,
+        })
+    }
+
+    fn put(&self, _url: &str, _entry: &CacheEntry) {}
+
+    fn touch(&self, _url: &str, _expires: Option<SystemTime>) {}
+}
+
+/// A loader that gets every tile from [`PatternTiles`].
+pub fn pattern_loader() -> DefaultLoaderInterface {
+    DefaultLoaderInterface::with_cache(Some(Arc::new(PatternTiles)), false)
+}
+
+/// A loader that gets every tile from [`SolidTiles`] of `color`.
+pub fn solid_loader(color: Rgba<u8>) -> DefaultLoaderInterface {
+    DefaultLoaderInterface::with_cache(Some(SolidTiles::new(color)), false)
+        .with_decoded_cache(Arc::new(DecodedTileCache::new(16 * 1024 * 1024)))
+}
+
+/// Raster layer config for the tiles of `matrix_set`, with URLs that only [`SolidTiles`]
+/// can serve.
+pub struct TestConfig {
+    pub crs: String,
+    pub matrix_set: TileMatrixSet,
+}
+
+impl TestConfig {
+    pub fn new(matrix_set: TileMatrixSet) -> Self {
+        Self {
+            crs: matrix_set.crs.clone(),
+            matrix_set,
+        }
+    }
+}
+
+impl Tiled2dMapLayerConfigTrait for TestConfig {
+    fn getCoordinateSystemIdentifier(&self) -> UniquePtr<cxx::CxxString> {
+        make_string(&self.crs)
+    }
+
+    fn getTileUrl(&self, x: i32, y: i32, _t: i32, zoom: i32) -> UniquePtr<cxx::CxxString> {
+        make_string(&format!("test://{zoom}/{x}/{y}"))
+    }
+
+    fn getZoomLevelInfos(&self) -> UniquePtr<CxxVector<Tiled2dMapZoomLevelInfo>> {
+        self.matrix_set.zoom_level_infos()
+    }
+
+    fn getZoomInfo(&self) -> UniquePtr<Tiled2dMapZoomInfo> {
+        default_zoom_info()
+    }
+
+    fn getLayerName(&self) -> UniquePtr<cxx::CxxString> {
+        make_string("test")
+    }
+}
@@ -0,0 +1,71 @@
+// Copyright (c) 2023 Ubique Innovation AG <https://www.ubique.ch>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+mod common;
+
+use common::{solid_loader, TestConfig, TILE_COLOR};
+use openmobilemaps_rs::openmobilemaps_sys::openmobilemaps_bindings::{
+    raster::TileMatrixSet, Coordinate,
+};
+use openmobilemaps_rs::renderer::{CoordinateSystem, View};
+use openmobilemaps_rs::MapRenderer;
+
+const BERN: Coordinate = Coordinate::Lv95 {
+    e: 2600670.0,
+    n: 1199655.0,
+};
+
+#[test]
+fn renders_lv95_layer_on_lv95_map() {
+    let mut renderer = MapRenderer::builder((256, 256))
+        .coordinate_system(CoordinateSystem::Epsg2056)
+        .build()
+        .unwrap();
+    renderer
+        .add_raster_layer(
+            Box::new(TestConfig::new(TileMatrixSet::swiss_lv95())),
+            Box::new(solid_loader(TILE_COLOR)),
+        )
+        .unwrap();
+    renderer.set_view(View::Center {
+        center: BERN,
+        zoom: 50000.0,
+        rotation: 0.0,
+    });
+
+    let frame = renderer.render().unwrap();
+    for (x, y) in [(0, 0), (128, 128), (255, 255)] {
+        assert_eq!(*frame.get_pixel(x, y), TILE_COLOR, "pixel {x}, {y}");
+    }
+}
+
+#[test]
+fn rejects_layer_in_unknown_crs() {
+    let mut renderer = MapRenderer::new((64, 64)).unwrap();
+    let mut config = TestConfig::new(TileMatrixSet::web_mercator_quad(0..=3, 256));
+    config.crs = "EPSG:9999".into();
+
+    let result = renderer.add_raster_layer(Box::new(config), Box::new(solid_loader(TILE_COLOR)));
+    assert!(result.is_err());
+}
+
+#[test]
+fn rejects_lv95_layer_on_custom_map() {
+    let mut renderer = MapRenderer::builder((64, 64))
+        .coordinate_system(CoordinateSystem::Custom {
+            identifier: "test:grid".into(),
+            bounds: [0.0, 0.0, 1000.0, 1000.0],
+            unit_to_screen_meter_factor: 1.0,
+        })
+        .build()
+        .unwrap();
+
+    let result = renderer.add_raster_layer(
+        Box::new(TestConfig::new(TileMatrixSet::swiss_lv95())),
+        Box::new(solid_loader(TILE_COLOR)),
+    );
+    assert!(result.is_err());
+}
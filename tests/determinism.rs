@@ -0,0 +1,82 @@
+// Copyright (c) 2023 Ubique Innovation AG <https://www.ubique.ch>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+mod common;
+
+use common::{pattern_loader, TestConfig};
+use image::RgbaImage;
+use openmobilemaps_rs::openmobilemaps_sys::openmobilemaps_bindings::{
+    line::{LineLayer, LineStyleOptions},
+    raster::TileMatrixSet,
+    Coordinate, SchedulerBuilder,
+};
+use openmobilemaps_rs::renderer::View;
+use openmobilemaps_rs::MapRenderer;
+
+/// Renders tiles and a line on a fresh renderer that runs all tasks on the render thread.
+fn render() -> RgbaImage {
+    let mut renderer = MapRenderer::builder((512, 512))
+        .scheduler(SchedulerBuilder::new().deterministic())
+        .build()
+        .unwrap();
+    renderer
+        .add_raster_layer(
+            Box::new(TestConfig::new(TileMatrixSet::web_mercator_quad(
+                0..=6,
+                256,
+            ))),
+            Box::new(pattern_loader()),
+        )
+        .unwrap();
+    let mut lines = LineLayer::new().unwrap();
+    lines
+        .add_line(
+            "line",
+            &[
+                Coordinate::Wgs84 {
+                    lat: 45.0,
+                    lon: 5.0,
+                },
+                Coordinate::Wgs84 {
+                    lat: 48.0,
+                    lon: 11.0,
+                },
+            ],
+            &LineStyleOptions {
+                width: 4.0,
+                ..Default::default()
+            }
+            .dashed(2.0, 1.0),
+        )
+        .unwrap();
+    renderer.add_layer(lines.layer_interface());
+    renderer.set_view(View::Center {
+        center: Coordinate::Wgs84 {
+            lat: 46.5,
+            lon: 8.0,
+        },
+        zoom: 5e6,
+        rotation: 0.0,
+    });
+    renderer.render().unwrap()
+}
+
+#[test]
+fn deterministic_renders_are_identical() {
+    let first = render();
+    let second = render();
+    assert_eq!(first.dimensions(), second.dimensions());
+    assert!(
+        first.as_raw() == second.as_raw(),
+        "frames differ in {} bytes",
+        first
+            .as_raw()
+            .iter()
+            .zip(second.as_raw())
+            .filter(|(a, b)| a != b)
+            .count()
+    );
+}
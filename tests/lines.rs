@@ -0,0 +1,106 @@
+// Copyright (c) 2023 Ubique Innovation AG <https://www.ubique.ch>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use image::{Rgba, RgbaImage};
+use openmobilemaps_rs::openmobilemaps_sys::openmobilemaps_bindings::{
+    line::{LineCap, LineLayer, LineStyleOptions},
+    Coordinate,
+};
+use openmobilemaps_rs::renderer::View;
+use openmobilemaps_rs::MapRenderer;
+
+const VIEW_PORT: (usize, usize) = (256, 64);
+const LINE_COLOR: Rgba<u8> = Rgba([0, 0, 255, 255]);
+const GAP_COLOR: Rgba<u8> = Rgba([0, 255, 0, 255]);
+
+/// Renders a horizontal line through the middle of the frame, longer than the frame is wide.
+fn render_line(style: &LineStyleOptions) -> RgbaImage {
+    let mut renderer = MapRenderer::new(VIEW_PORT).unwrap();
+    let mut lines = LineLayer::new().unwrap();
+    lines
+        .add_line(
+            "line",
+            &[
+                Coordinate::WebMercator { x: -1e7, y: 0.0 },
+                Coordinate::WebMercator { x: 1e7, y: 0.0 },
+            ],
+            style,
+        )
+        .unwrap();
+    renderer.add_layer(lines.layer_interface());
+    renderer.set_view(View::Center {
+        center: Coordinate::WebMercator { x: 0.0, y: 0.0 },
+        zoom: 1e5,
+        rotation: 0.0,
+    });
+    renderer.render().unwrap()
+}
+
+fn is_close(pixel: Rgba<u8>, color: Rgba<u8>) -> bool {
+    pixel
+        .0
+        .iter()
+        .zip(color.0)
+        .all(|(actual, expected)| actual.abs_diff(expected) <= 8)
+}
+
+/// Pixels along the middle of the line, leaving out the edges of the frame.
+fn center_row(frame: &RgbaImage) -> Vec<Rgba<u8>> {
+    let y = VIEW_PORT.1 as u32 / 2;
+    (8..VIEW_PORT.0 as u32 - 8)
+        .map(|x| *frame.get_pixel(x, y))
+        .collect()
+}
+
+fn style() -> LineStyleOptions {
+    LineStyleOptions {
+        color: [0.0, 0.0, 1.0, 1.0],
+        gap_color: [0.0, 1.0, 0.0, 1.0],
+        width: 8.0,
+        cap: LineCap::Butt,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn dashed_line_has_gaps() {
+    // dashes and gaps of 16 pixels
+    let frame = render_line(&style().dashed(2.0, 2.0));
+    let row = center_row(&frame);
+
+    let dashes = row
+        .iter()
+        .filter(|&&pixel| is_close(pixel, LINE_COLOR))
+        .count();
+    let gaps = row
+        .iter()
+        .filter(|&&pixel| is_close(pixel, GAP_COLOR))
+        .count();
+    assert!(
+        dashes + gaps >= row.len() * 9 / 10,
+        "{dashes} dash and {gaps} gap pixels"
+    );
+    assert!(
+        gaps >= row.len() / 3 && dashes >= row.len() / 3,
+        "{dashes} dash and {gaps} gap pixels"
+    );
+    // the pattern repeats within the frame instead of switching color once
+    let changes = row
+        .windows(2)
+        .filter(|pair| is_close(pair[0], LINE_COLOR) != is_close(pair[1], LINE_COLOR))
+        .count();
+    assert!(changes >= 8, "{changes} changes between dashes and gaps");
+}
+
+#[test]
+fn empty_dash_draws_solid_line() {
+    let frame = render_line(&style());
+    assert!(style().dash.is_empty());
+
+    for (x, pixel) in center_row(&frame).into_iter().enumerate() {
+        assert!(is_close(pixel, LINE_COLOR), "{pixel:?} at {x}");
+    }
+}
@@ -0,0 +1,70 @@
+// Copyright (c) 2023 Ubique Innovation AG <https://www.ubique.ch>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{solid_loader, TestConfig, TILE_COLOR};
+use openmobilemaps_rs::openmobilemaps_sys::openmobilemaps_bindings::{
+    raster::TileMatrixSet, Coordinate,
+};
+use openmobilemaps_rs::renderer::View;
+use openmobilemaps_rs::{MapRenderer, RenderSession};
+
+const VIEW_PORT: (usize, usize) = (512, 512);
+const FRAMES: u32 = 10;
+
+/// A different view for every frame, so that no two frames show the same tiles.
+fn view(frame: u32) -> View {
+    View::Center {
+        center: Coordinate::Wgs84 {
+            lat: 46.0 + frame as f64 * 0.2,
+            lon: 7.0 + frame as f64 * 0.2,
+        },
+        zoom: 2e6,
+        rotation: 0.0,
+    }
+}
+
+fn renderer() -> MapRenderer {
+    let mut renderer = MapRenderer::new(VIEW_PORT).unwrap();
+    renderer
+        .add_raster_layer(
+            Box::new(TestConfig::new(TileMatrixSet::web_mercator_quad(
+                0..=12,
+                256,
+            ))),
+            Box::new(solid_loader(TILE_COLOR)),
+        )
+        .unwrap();
+    renderer
+}
+
+#[test]
+#[ignore = "timing test, run in release mode with --ignored"]
+fn warm_session_is_faster_than_cold_renders() {
+    let mut cold = Duration::ZERO;
+    for frame in 0..FRAMES {
+        let start = Instant::now();
+        let mut renderer = renderer();
+        renderer.set_view(view(frame));
+        renderer.render().unwrap();
+        cold += start.elapsed();
+    }
+    let cold_average = cold / FRAMES;
+
+    let mut session = RenderSession::from_renderer(renderer());
+    for frame in 0..=FRAMES {
+        session.render_frame(view(frame)).unwrap();
+    }
+    let warm_average = session.stats().warm_average().unwrap();
+
+    assert!(
+        warm_average < cold_average,
+        "warm frames took {warm_average:?} on average, cold renders {cold_average:?}"
+    );
+}